@@ -0,0 +1,338 @@
+// Path: src/attributes.rs
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::progress::Skill;
+use crate::status::EffectKind;
+use crate::{combat, Character, GameObject, TerrainType};
+
+// ===========================================================================
+// Attributes
+// a character has strength, dexterity and intelligence, each a base value plus modifiers.
+// modifiers come from what the character carries, the ground it stands on, the spells and
+// the status effects on it, and last for a number of turns or for good.
+// max health, max mana, how much can be carried, what a step costs and the chance to hit
+// all follow from the attributes, and every value can say where it comes from.
+
+// health and mana with no strength or intelligence behind them
+const BASE_HEALTH: i32 = 50;
+const HEALTH_PER_STRENGTH: i32 = 5;
+const MANA_PER_INTELLIGENCE: i32 = 5;
+// items carried without any strength, and more for every two points of it
+const BASE_CARRY: i32 = 5;
+const STRENGTH_PER_CARRY: i32 = 2;
+// energy spent on a step on the flat, and more for every item carried over capacity
+const BASE_MOVE_COST: i32 = 1;
+// the chance to hit, in percent, when accuracy and evasion are even
+pub const BASE_HIT_CHANCE: i32 = 70;
+pub const HIT_CHANCE_PER_POINT: i32 = 3;
+// the evasion of a foe the hit chance in `me` is worked out against
+const AVERAGE_EVASION: i32 = 10;
+// a weapon adds a point of strength for each this much it is worth
+const WEAPON_VALUE_PER_STRENGTH: i32 = 10;
+// how much a potion raises strength, for each this much it is worth, and for how long
+const POTION_VALUE_PER_STRENGTH: i32 = 5;
+const POTION_TURNS: i32 = 20;
+// bad footing takes this much dexterity away
+const FOOTING_PENALTY: i32 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Attribute {
+    Strength,
+    Dexterity,
+    Intelligence,
+}
+
+impl Attribute {
+    pub const ALL: [Attribute; 3] = [Attribute::Strength, Attribute::Dexterity, Attribute::Intelligence];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Attribute::Strength => "strength",
+            Attribute::Dexterity => "dexterity",
+            Attribute::Intelligence => "intelligence",
+        }
+    }
+}
+
+impl FromStr for Attribute {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Attribute::ALL.into_iter()
+            .find(|attribute| attribute.name() == s)
+            .ok_or_else(|| format!("Unknown attribute: {}", s))
+    }
+}
+
+// where a modifier comes from
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+    Item(String),
+    Spell(String),
+    Terrain(TerrainType),
+    Effect(EffectKind),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Item(name) => write!(f, "{}", name),
+            Source::Spell(name) => write!(f, "spell of {}", name),
+            Source::Terrain(terrain_type) => write!(f, "footing on {:?}", terrain_type),
+            Source::Effect(kind) => write!(f, "being {:?}", kind),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Modifier {
+    pub attribute: Attribute,
+    pub amount: i32,
+    pub source: Source,
+    // the turns left, None when it lasts for good
+    pub turns: Option<i32>,
+}
+
+// the base attributes of a character and the modifiers put on it,
+// modifiers from its bag and the ground under it are worked out as they are needed
+#[derive(Clone, Debug, PartialEq)]
+pub struct Attributes {
+    strength: i32,
+    dexterity: i32,
+    intelligence: i32,
+    modifiers: Vec<Modifier>,
+}
+
+impl Attributes {
+    pub fn new(strength: i32, dexterity: i32, intelligence: i32) -> Self {
+        Attributes { strength, dexterity, intelligence, modifiers: Vec::new() }
+    }
+
+    pub fn base(&self, attribute: Attribute) -> i32 {
+        match attribute {
+            Attribute::Strength => self.strength,
+            Attribute::Dexterity => self.dexterity,
+            Attribute::Intelligence => self.intelligence,
+        }
+    }
+
+    // one point more on an attribute, for good
+    pub fn raise(&self, attribute: Attribute) -> Self {
+        let mut raised = self.clone();
+        match attribute {
+            Attribute::Strength => raised.strength += 1,
+            Attribute::Dexterity => raised.dexterity += 1,
+            Attribute::Intelligence => raised.intelligence += 1,
+        }
+        raised
+    }
+
+    pub fn with_modifier(&self, modifier: Modifier) -> Self {
+        let mut modifiers = self.modifiers.clone();
+        modifiers.push(modifier);
+        Attributes { modifiers, ..self.clone() }
+    }
+
+    // a turn off every modifier that wears off, those with none left are gone
+    pub fn tick(&self) -> Self {
+        let modifiers = self.modifiers.iter()
+            .map(|modifier| Modifier { turns: modifier.turns.map(|turns| turns - 1), ..modifier.clone() })
+            .filter(|modifier| modifier.turns.is_none_or(|turns| turns > 0))
+            .collect();
+        Attributes { modifiers, ..self.clone() }
+    }
+}
+
+// a value and the parts it is made of
+#[derive(Clone, Debug, PartialEq)]
+pub struct Breakdown {
+    pub name: &'static str,
+    pub total: i32,
+    parts: Vec<(String, i32)>,
+    unit: &'static str,
+}
+
+impl Breakdown {
+    fn new(name: &'static str, unit: &'static str) -> Self {
+        Breakdown { name, total: 0, parts: Vec::new(), unit }
+    }
+
+    fn add(mut self, part: impl Into<String>, amount: i32) -> Self {
+        self.total += amount;
+        self.parts.push((part.into(), amount));
+        self
+    }
+}
+
+// strength 12 (10 base, +2 Sword)
+impl fmt::Display for Breakdown {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}{} (", self.name, self.total, self.unit)?;
+        for (index, (part, amount)) in self.parts.iter().enumerate() {
+            let sign = if index > 0 && *amount >= 0 { "+" } else { "" };
+            write!(f, "{}{}{}{} {}", if index > 0 { ", " } else { "" }, sign, amount, self.unit, part)?;
+        }
+        write!(f, ")")
+    }
+}
+
+// every modifier on a character right now
+pub fn modifiers(character: &Character) -> Vec<Modifier> {
+    let mut modifiers = character.attributes.modifiers.clone();
+    if let Some(weapon) = combat::weapon(character) {
+        let amount = weapon.value / WEAPON_VALUE_PER_STRENGTH;
+        if amount > 0 {
+            modifiers.push(Modifier { attribute: Attribute::Strength, amount, source: Source::Item(weapon.name.clone()), turns: None });
+        }
+    }
+    let terrain_type = character.get_tile(character.x_position, character.y_position).terrain_type;
+    if matches!(terrain_type, TerrainType::Swamp | TerrainType::Snow | TerrainType::River | TerrainType::Lake) {
+        modifiers.push(Modifier { attribute: Attribute::Dexterity, amount: -FOOTING_PENALTY, source: Source::Terrain(terrain_type), turns: None });
+    }
+    for effect in character.effects.iter() {
+        for (attribute, amount) in effect.kind.modifiers() {
+            modifiers.push(Modifier { attribute: *attribute, amount: amount * effect.stacks, source: Source::Effect(effect.kind), turns: Some(effect.turns) });
+        }
+    }
+    modifiers
+}
+
+pub fn attribute(character: &Character, attribute: Attribute) -> Breakdown {
+    let breakdown = Breakdown::new(attribute.name(), "").add("base", character.attributes.base(attribute));
+    modifiers(character).into_iter()
+        .filter(|modifier| modifier.attribute == attribute)
+        .fold(breakdown, |breakdown, modifier| {
+            let lasting = modifier.turns.map(|turns| format!(" for {} turns", turns)).unwrap_or_default();
+            breakdown.add(format!("{}{}", modifier.source, lasting), modifier.amount)
+        })
+}
+
+pub fn value(character: &Character, of: Attribute) -> i32 {
+    attribute(character, of).total.max(0)
+}
+
+pub fn max_health(character: &Character) -> Breakdown {
+    Breakdown::new("max health", "")
+        .add("base", BASE_HEALTH)
+        .add("from strength", value(character, Attribute::Strength) * HEALTH_PER_STRENGTH)
+}
+
+pub fn max_mana(character: &Character) -> Breakdown {
+    Breakdown::new("max mana", "")
+        .add("from intelligence", value(character, Attribute::Intelligence) * MANA_PER_INTELLIGENCE)
+}
+
+// how many items can be carried before every step costs more
+pub fn carry_capacity(character: &Character) -> Breakdown {
+    Breakdown::new("carry capacity", " items")
+        .add("base", BASE_CARRY)
+        .add("from strength", value(character, Attribute::Strength) / STRENGTH_PER_CARRY)
+}
+
+// the energy a step on the flat costs, climbing costs more on top, see Character::do_move
+pub fn move_cost(character: &Character) -> Breakdown {
+    let over = character.bag.len() as i32 - carry_capacity(character).total;
+    let breakdown = Breakdown::new("move cost", " energy").add("base", BASE_MOVE_COST);
+    if over > 0 { breakdown.add("carrying too much", over) } else { breakdown }
+}
+
+// how good a character is at landing a blow, and at not being hit
+pub fn accuracy(character: &Character) -> i32 {
+    character.attack + value(character, Attribute::Dexterity) / 2 + swordsmanship(character)
+}
+
+// swordsmanship only counts with a weapon in hand
+pub fn swordsmanship(character: &Character) -> i32 {
+    combat::weapon(character).map_or(0, |_| character.progress.skill(Skill::Swordsmanship) as i32)
+}
+
+pub fn evasion(character: &Character) -> i32 {
+    combat::defense(character) + value(character, Attribute::Dexterity) / 2
+}
+
+// the chance to hit a foe of average evasion
+pub fn hit_chance(character: &Character) -> Breakdown {
+    Breakdown::new("hit chance", "%")
+        .add("base", BASE_HIT_CHANCE)
+        .add("from attack", character.attack * HIT_CHANCE_PER_POINT)
+        .add("from dexterity", value(character, Attribute::Dexterity) / 2 * HIT_CHANCE_PER_POINT)
+        .add("from swordsmanship", swordsmanship(character) * HIT_CHANCE_PER_POINT)
+        .add("against an average foe", -AVERAGE_EVASION * HIT_CHANCE_PER_POINT)
+}
+
+// a potion raises strength for a while
+pub fn potion(name: &str, value: i32) -> Modifier {
+    Modifier {
+        attribute: Attribute::Strength,
+        amount: (value / POTION_VALUE_PER_STRENGTH).max(1),
+        source: Source::Spell(name.to_string()),
+        turns: Some(POTION_TURNS),
+    }
+}
+
+// every attribute and derived stat, one to a line, for the `me` command
+pub fn describe(character: &Character) -> String {
+    let mut lines: Vec<String> = Attribute::ALL.iter().map(|of| attribute(character, *of).to_string()).collect();
+    lines.push(format!("mana {} of {}", character.mana, max_mana(character)));
+    lines.push(format!("health {} of {}", character.health, max_health(character)));
+    lines.push(carry_capacity(character).to_string());
+    lines.push(move_cost(character).to_string());
+    lines.push(hit_chance(character).to_string());
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{character, small_config};
+    use crate::{CharacterType, GameMap, Item, ItemType};
+
+    fn player() -> Character {
+        character("PlayerOne", CharacterType::Player, &GameMap::new(small_config(42)), 64, 64)
+    }
+
+    fn modifier(amount: i32, turns: Option<i32>) -> Modifier {
+        Modifier { attribute: Attribute::Strength, amount, source: Source::Spell("Might".to_string()), turns }
+    }
+
+    #[test]
+    fn timed_modifiers_wear_off_and_lasting_ones_stay() {
+        let attributes = Attributes::new(10, 10, 10)
+            .with_modifier(modifier(1, Some(1)))
+            .with_modifier(modifier(2, Some(2)))
+            .with_modifier(modifier(3, None));
+        let once = attributes.tick();
+        assert_eq!(once.modifiers, vec![modifier(2, Some(1)), modifier(3, None)]);
+        let twice = once.tick();
+        assert_eq!(twice.modifiers, vec![modifier(3, None)]);
+        assert_eq!(twice.tick(), twice);
+    }
+
+    #[test]
+    fn a_value_says_where_it_comes_from() {
+        let player = player();
+        let armed = Character { bag: vec![Item::new(ItemType::Weapon, "Sword", 20, 64, 64)], ..player.clone() };
+        assert_eq!(attribute(&armed, Attribute::Strength).to_string(), "strength 12 (10 base, +2 Sword)");
+
+        let cursed = Character { attributes: player.attributes.with_modifier(modifier(-3, Some(4))), ..player };
+        assert_eq!(attribute(&cursed, Attribute::Strength).to_string(), "strength 7 (10 base, -3 spell of Might for 4 turns)");
+        assert_eq!(max_health(&cursed).total, BASE_HEALTH + 7 * HEALTH_PER_STRENGTH);
+    }
+
+    #[test]
+    fn carrying_too_much_makes_every_step_cost_more() {
+        let player = player();
+        let capacity = carry_capacity(&player).total;
+        assert_eq!(capacity, BASE_CARRY + 10 / STRENGTH_PER_CARRY);
+        let carrying = |items: i32| Character {
+            bag: (0..items).map(|_| Item::new(ItemType::Food, "Apple", 10, 64, 64)).collect(),
+            ..player.clone()
+        };
+        assert_eq!(move_cost(&carrying(capacity)).total, BASE_MOVE_COST);
+        let laden = move_cost(&carrying(capacity + 2));
+        assert_eq!(laden.total, BASE_MOVE_COST + 2);
+        assert_eq!(laden.to_string(), "move cost 3 energy (1 energy base, +2 energy carrying too much)");
+    }
+}
@@ -0,0 +1,71 @@
+// Path: src/biome.rs
+
+use std::cmp::max;
+use noise::Perlin;
+
+use crate::shape;
+use crate::world_gen::{WorldGenConfig, WorldNoise};
+use crate::TerrainType;
+
+// ===========================================================================
+// Climate and biomes
+// moisture and temperature are independent noise fields, on a 0-100 scale.
+// land is classified by looking both up in a Whittaker style table.
+
+// below this temperature the land is cold, at or above HOT it is hot
+const COLD: i32 = 35;
+const HOT: i32 = 65;
+// below this moisture the land is dry, at or above WET it is wet
+const DRY: i32 = 33;
+const WET: i32 = 66;
+// colder than this is always snow, whatever the moisture
+const FREEZING: i32 = 12;
+// wet ground no higher than this above the sea turns to swamp
+const SWAMP_MAX_ELEVATION: i32 = 2;
+// how many degrees colder the highest land is than the shore
+const LAPSE: f64 = 16.0;
+
+// rows are temperature bands (cold, temperate, hot),
+// columns are moisture bands (dry, moderate, wet)
+const BIOME_TABLE: [[TerrainType; 3]; 3] = [
+    [TerrainType::Tundra, TerrainType::Tundra, TerrainType::Snow],
+    [TerrainType::Earth, TerrainType::Grass, TerrainType::Forest],
+    [TerrainType::Desert, TerrainType::Grass, TerrainType::Jungle],
+];
+
+// two octaves of noise scaled to 0-1
+fn sample(perlin: &Perlin, config: &WorldGenConfig, x: f64, y: f64) -> f64 {
+    let frequency = config.climate_frequency;
+    let value = shape::sample(config, perlin, x, y, frequency) + 0.5 * shape::sample(config, perlin, x, y, frequency * 2.0);
+    ((value / 1.5 + 1.0) / 2.0).clamp(0.0, 1.0)
+}
+
+pub fn moisture_at(config: &WorldGenConfig, noise: &WorldNoise, x: i32, y: i32) -> i32 {
+    (sample(&noise.moisture, config, x as f64, y as f64) * 100.0) as i32
+}
+
+// the north of the map is colder than the south, and high ground is colder than low ground
+pub fn temperature_at(config: &WorldGenConfig, noise: &WorldNoise, x: i32, y: i32, elevation: i32) -> i32 {
+    let latitude = y as f64 / config.height as f64;
+    let local = sample(&noise.temperature, config, x as f64, y as f64);
+    let height = max(0, elevation) as f64 / (config.elevation_scale - config.sea_level).max(1.0);
+    let temperature = (0.5 * local + 0.5 * latitude) * 100.0 - height * LAPSE;
+    temperature.clamp(0.0, 100.0) as i32
+}
+
+// pick the terrain for a dry land tile that is not a beach
+pub fn classify(elevation: i32, moisture: i32, temperature: i32, config: &WorldGenConfig) -> TerrainType {
+    if temperature < FREEZING {
+        return TerrainType::Snow;
+    }
+    if elevation >= config.mountain_threshold {
+        return if temperature < COLD { TerrainType::Snow } else { TerrainType::Mountain };
+    }
+    if moisture >= WET && temperature >= COLD && elevation <= SWAMP_MAX_ELEVATION {
+        return TerrainType::Swamp;
+    }
+
+    let temperature_band = if temperature < COLD { 0 } else if temperature < HOT { 1 } else { 2 };
+    let moisture_band = if moisture < DRY { 0 } else if moisture < WET { 1 } else { 2 };
+    BIOME_TABLE[temperature_band][moisture_band]
+}
@@ -0,0 +1,163 @@
+// Path: src/combat.rs
+
+use rand::Rng;
+
+use crate::attributes;
+use crate::{Character, Direction, Item, ItemType};
+
+// ===========================================================================
+// Combat
+// a character strikes at another standing next to it, on the same level.
+// whether the blow lands depends on the attacker's accuracy against the defender's evasion,
+// which come from attack, defense and dexterity, see attributes.rs.
+// how hard it lands depends on the attack, the weapon in hand and the defense again.
+// a character that defends stands ready until its next turn, which makes it harder to hit and hurt.
+// a character fights with the best weapon in its bag.
+
+// however lopsided a fight, a blow can always miss or land, in percent
+const MIN_HIT_CHANCE: i32 = 5;
+const MAX_HIT_CHANCE: i32 = 95;
+// a weapon adds a point of damage for each this much it is worth
+const WEAPON_VALUE_PER_DAMAGE: i32 = 5;
+// a defending character's defense is raised by this much
+const DEFEND_BONUS: i32 = 5;
+
+// what came of one strike
+#[derive(Clone, Debug, PartialEq)]
+pub struct Blow {
+    pub hit: bool,
+    pub damage: i32,
+    // the name of the weapon struck with, if any
+    pub weapon: Option<String>,
+}
+
+// the weapon a character fights with, the most valuable one in its bag
+pub fn weapon(character: &Character) -> Option<&Item> {
+    character.bag.iter()
+        .filter(|item| item.item_type == ItemType::Weapon)
+        .max_by_key(|item| item.value)
+}
+
+// a character's defense, with its stance
+pub fn defense(character: &Character) -> i32 {
+    character.defense + if character.defending { DEFEND_BONUS } else { 0 }
+}
+
+// each point of accuracy over the defender's evasion makes a hit more likely, each point under less
+pub fn hit_chance(attacker: &Character, defender: &Character) -> f64 {
+    let edge = attributes::accuracy(attacker) - attributes::evasion(defender);
+    let percent = (attributes::BASE_HIT_CHANCE + edge * attributes::HIT_CHANCE_PER_POINT).clamp(MIN_HIT_CHANCE, MAX_HIT_CHANCE);
+    percent as f64 / 100.0
+}
+
+// roll for one strike, a blow that lands always does at least a point of damage
+pub fn strike<R: Rng>(rng: &mut R, attacker: &Character, defender: &Character) -> Blow {
+    let weapon = weapon(attacker);
+    if !rng.gen_bool(hit_chance(attacker, defender)) {
+        return Blow { hit: false, damage: 0, weapon: weapon.map(|item| item.name.clone()) };
+    }
+    // a skilled swordsman makes more of a weapon
+    let bonus = weapon.map_or(0, |item| item.value / WEAPON_VALUE_PER_DAMAGE) + attributes::swordsmanship(attacker) / 2;
+    let roll = rng.gen_range(1..=(attacker.attack + bonus).max(1));
+    let damage = (roll - defense(defender) / 2).max(1);
+    Blow { hit: true, damage, weapon: weapon.map(|item| item.name.clone()) }
+}
+
+// the way from one character to the other when they are next to each other on the same level
+pub fn adjacent(from: &Character, to: &Character) -> Option<Direction> {
+    if from.depth != to.depth {
+        return None;
+    }
+    // on a wrap-around world the two can be either side of the seam
+    let config = from.game_map.config();
+    let dx = [-1, 0, 1].into_iter().find(|dx| config.wrap_x(from.x_position + dx) == to.x_position)?;
+    Direction::from_offset(dx, to.y_position - from.y_position)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::attributes::Attributes;
+    use crate::tests::{character, small_config};
+    use crate::world_gen::{WorldGenConfig, WorldShape};
+    use crate::{CharacterType, GameMap};
+
+    fn fighters() -> (Character, Character) {
+        let map = GameMap::new(small_config(42));
+        (character("PlayerOne", CharacterType::Player, &map, 64, 64), character("TrollOne", CharacterType::Troll, &map, 65, 64))
+    }
+
+    #[test]
+    fn hit_chance_is_kept_between_5_and_95_percent() {
+        let (attacker, defender) = fighters();
+        let sure = Character { attack: 100, ..attacker.clone() };
+        assert_eq!(hit_chance(&sure, &defender), 0.95);
+        let hopeless = Character { attack: 0, ..attacker.clone() };
+        let wall = Character { defense: 100, ..defender.clone() };
+        assert_eq!(hit_chance(&hopeless, &wall), 0.05);
+        let even = hit_chance(&attacker, &defender);
+        assert!(0.05 < even && even < 0.95);
+    }
+
+    #[test]
+    fn a_blow_that_lands_does_at_least_a_point_of_damage() {
+        let (attacker, defender) = fighters();
+        // sure to land and sure to do nothing against the defense
+        let weak = Character { attack: 1, attributes: Attributes::new(10, 200, 10), ..attacker };
+        let wall = Character { defense: 50, ..defender };
+        let mut rng = StdRng::seed_from_u64(7);
+        let blows: Vec<Blow> = (0..100).map(|_| strike(&mut rng, &weak, &wall)).collect();
+        assert!(blows.iter().filter(|blow| blow.hit).count() > 80);
+        assert!(blows.iter().all(|blow| blow.damage == if blow.hit { 1 } else { 0 }));
+    }
+
+    #[test]
+    fn defending_makes_a_character_harder_to_hit() {
+        let (attacker, defender) = fighters();
+        let ready = Character { defending: true, ..defender.clone() };
+        assert_eq!(defense(&ready), defense(&defender) + DEFEND_BONUS);
+        let drop = hit_chance(&attacker, &defender) - hit_chance(&attacker, &ready);
+        assert!((drop - (DEFEND_BONUS * attributes::HIT_CHANCE_PER_POINT) as f64 / 100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn the_same_seed_strikes_the_same_blows() {
+        let (attacker, defender) = fighters();
+        let sword = Item::new(ItemType::Weapon, "Sword", 15, 64, 64);
+        let armed = Character { bag: vec![sword], ..attacker };
+        let blows = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..50).map(|_| strike(&mut rng, &armed, &defender)).collect::<Vec<Blow>>()
+        };
+        assert_eq!(blows(3), blows(3));
+        assert!(blows(3).iter().all(|blow| blow.weapon.as_deref() == Some("Sword")));
+        let most = armed.attack + 15 / WEAPON_VALUE_PER_DAMAGE;
+        assert!(blows(3).iter().all(|blow| !blow.hit || (1..=most).contains(&blow.damage)));
+    }
+
+    #[test]
+    fn characters_either_side_of_the_wrap_seam_are_next_to_each_other() {
+        let config = WorldGenConfig { shape: WorldShape::Wrap, ..small_config(42) };
+        let map = GameMap::new(config.clone());
+        let west = character("PlayerOne", CharacterType::Player, &map, 0, 64);
+        let east = character("TrollOne", CharacterType::Troll, &map, config.width - 1, 65);
+        assert_eq!(adjacent(&west, &east), Some(Direction::SouthWest));
+        assert_eq!(adjacent(&east, &west), Some(Direction::NorthEast));
+
+        // not on a world with edges
+        let (player, _) = fighters();
+        let far = Character { x_position: config.width - 1, ..player.clone() };
+        assert_eq!(adjacent(&Character { x_position: 0, ..player.clone() }, &far), None);
+    }
+
+    #[test]
+    fn only_characters_on_the_same_level_are_next_to_each_other() {
+        let (player, troll) = fighters();
+        assert_eq!(adjacent(&player, &troll), Some(Direction::East));
+        assert_eq!(adjacent(&player, &Character { depth: 1, ..troll.clone() }), None);
+        assert_eq!(adjacent(&player, &Character { x_position: 66, ..troll }), None);
+    }
+}
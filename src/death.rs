@@ -0,0 +1,255 @@
+// Path: src/death.rs
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::attributes;
+use crate::{Character, CharacterType, GameMap, GameObject, Item, Tile, World};
+
+// ===========================================================================
+// Death
+// a character whose health reaches 0, from a blow or from poison, hunger or thirst, dies.
+// it leaves a corpse behind where it fell, holding everything that was in its bag.
+// a monster comes back at a monster spawn point after a while, or is gone for good when there are none.
+// the player comes back at once at the respawn point, empty handed, weakened,
+// and with some of the experience towards the next level lost.
+// every death is kept as an event until the command loop reports it.
+
+// the turns a monster stays dead before it comes back
+pub const MONSTER_RESPAWN_TURNS: i32 = 20;
+// the energy and hydration of a character that has rested
+const RESTED: i32 = 1000;
+// the part of the experience towards the next level the player loses, in percent
+pub const EXPERIENCE_PENALTY: u32 = 50;
+
+// what a dead character leaves behind
+#[derive(Clone, Debug, PartialEq)]
+pub struct Corpse {
+    pub name: String,
+    pub bag: Vec<Item>,
+    pub x_position: i32,
+    pub y_position: i32,
+    pub depth: i32,
+    // the map the corpse is lying on
+    game_map: GameMap,
+}
+
+impl Corpse {
+    pub fn of(character: &Character) -> Self {
+        Corpse {
+            name: character.name.clone(),
+            bag: character.bag.iter()
+                .map(|item| Item { x_position: character.x_position, y_position: character.y_position, ..item.clone() })
+                .collect(),
+            x_position: character.x_position,
+            y_position: character.y_position,
+            depth: character.depth,
+            game_map: character.game_map.clone(),
+        }
+    }
+}
+
+impl GameObject for Corpse {
+    fn update(&self, _world: &World) -> Self {
+        (*self).clone()
+    }
+
+    fn get_tile(&self, x: i32, y: i32) -> Tile {
+        self.game_map.get_tile(x, y)
+    }
+}
+
+// what becomes of a dead character
+#[derive(Clone, Debug, PartialEq)]
+pub enum Fate {
+    // back at once at x,y
+    Respawned(i32, i32),
+    // back after a number of turns
+    Returns(i32),
+    Gone,
+}
+
+// a death, kept until it is reported
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeathEvent {
+    pub name: String,
+    pub character_type: CharacterType,
+    pub cause: String,
+    pub x_position: i32,
+    pub y_position: i32,
+    pub depth: i32,
+    pub level: u32,
+    // how many items were left on the corpse
+    pub items: usize,
+    pub fate: Fate,
+}
+
+// TrollOne the Troll died at (110, 1512) slain by PlayerOne, 0 items left on the corpse, back in 20 turns
+impl fmt::Display for DeathEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} the level {} {:?} died at ({}, {})", self.name, self.level, self.character_type, self.x_position, self.y_position)?;
+        if self.depth > 0 {
+            write!(f, " at depth {}", self.depth)?;
+        }
+        write!(f, " {}, {} items left on the corpse", self.cause, self.items)?;
+        match self.fate {
+            Fate::Respawned(x, y) => write!(f, ", back at ({}, {}) with {}% of the experience towards the next level lost", x, y, EXPERIENCE_PENALTY),
+            Fate::Returns(turns) => write!(f, ", back in {} turns", turns),
+            Fate::Gone => write!(f, ", gone for good"),
+        }
+    }
+}
+
+// a dead character waiting to come back
+#[derive(Clone, Debug, PartialEq)]
+pub struct Respawn {
+    pub character: Character,
+    pub turns: i32,
+}
+
+// what killed a character that was not struck down, from the effects on it
+pub fn cause(character: &Character) -> String {
+    if character.effects.is_empty() {
+        return "of its wounds".to_string();
+    }
+    let effects: Vec<String> = character.effects.iter().map(|effect| format!("{:?}", effect.kind)).collect();
+    format!("while {}", effects.join(" and "))
+}
+
+// a character as it comes back at x,y on the overworld: rested, healed and with nothing on it
+pub fn revive(character: &Character, overworld: &GameMap, x: i32, y: i32) -> Character {
+    let fresh = Character {
+        energy: RESTED,
+        hydration: RESTED,
+        x_position: x,
+        y_position: y,
+        depth: 0,
+        defending: false,
+        bag: Vec::new(),
+        effects: Arc::new(Vec::new()),
+        game_map: overworld.clone(),
+        ..character.clone()
+    };
+    let health = attributes::max_health(&fresh).total;
+    let mana = attributes::max_mana(&fresh).total;
+    Character { health, mana, ..fresh }.look_around()
+}
+
+// the player as it comes back, with half its health, energy and hydration and less experience
+pub fn revive_player(character: &Character, overworld: &GameMap, x: i32, y: i32) -> Character {
+    let revived = revive(character, overworld, x, y);
+    Character {
+        health: revived.health / 2,
+        energy: RESTED / 2,
+        hydration: RESTED / 2,
+        progress: revived.progress.setback(EXPERIENCE_PENALTY),
+        ..revived
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::Progress;
+    use crate::tests::{character, small_config, world};
+    use crate::world_gen::WorldGenConfig;
+    use crate::zones::SpawnKind;
+    use crate::ItemType;
+
+    fn troll(world: &World) -> Character {
+        let bag = vec![Item::new(ItemType::Weapon, "Club", 10, 0, 0), Item::new(ItemType::Food, "Apple", 10, 3, 4)];
+        Character { bag, health: 0, ..character("TrollOne", CharacterType::Troll, &world.game_map, 60, 62) }
+    }
+
+    fn is_alive(world: &World, name: &str) -> bool {
+        world.characters().any(|character| character.name == name)
+    }
+
+    #[test]
+    fn the_corpse_holds_the_bag_where_its_owner_fell() {
+        let mut world = world(small_config(42));
+        let troll = troll(&world);
+        world.die(troll.clone(), "of its wounds".to_string());
+        let corpse = world.corpses_at(60, 62, 0).next().expect("a corpse where the troll fell");
+        assert_eq!(corpse.name, "TrollOne");
+        assert_eq!(corpse.bag.iter().map(|item| item.name.as_str()).collect::<Vec<_>>(), ["Club", "Apple"]);
+        assert!(corpse.bag.iter().all(|item| (item.x_position, item.y_position) == (60, 62)));
+        assert_eq!(world.deaths[0].items, 2);
+    }
+
+    #[test]
+    fn a_monster_with_nowhere_to_come_back_is_gone() {
+        let mut world = world(WorldGenConfig { spawn_points: 0, ..small_config(42) });
+        assert!(world.spawn_points(SpawnKind::Monster).is_empty());
+        world.die(troll(&world), "of its wounds".to_string());
+        assert_eq!(world.deaths[0].fate, Fate::Gone);
+        assert!(world.respawns.is_empty());
+        for _ in 0..MONSTER_RESPAWN_TURNS {
+            world.bring_back();
+        }
+        assert!(!is_alive(&world, "TrollOne"));
+    }
+
+    #[test]
+    fn a_monster_comes_back_at_a_spawn_point_after_a_while() {
+        let mut world = world(small_config(42));
+        let spawn_points = world.spawn_points(SpawnKind::Monster);
+        assert!(!spawn_points.is_empty());
+        world.die(troll(&world), "of its wounds".to_string());
+        assert_eq!(world.deaths[0].fate, Fate::Returns(MONSTER_RESPAWN_TURNS));
+        for _ in 1..MONSTER_RESPAWN_TURNS {
+            world.bring_back();
+        }
+        assert!(!is_alive(&world, "TrollOne"));
+        world.bring_back();
+        let back = world.characters().find(|character| character.name == "TrollOne").expect("the troll back");
+        assert!(spawn_points.contains(&(back.x_position, back.y_position)));
+        assert_eq!(back.health, attributes::max_health(back).total);
+        assert!(back.bag.is_empty());
+        assert!(world.respawns.is_empty());
+    }
+
+    #[test]
+    fn the_player_comes_back_at_once_weakened() {
+        let mut world = world(small_config(42));
+        world.respawn_point = (70, 70);
+        let fallen = Character {
+            health: 0,
+            energy: 3,
+            depth: 1,
+            progress: Progress::default().gain(250),
+            bag: vec![Item::new(ItemType::Weapon, "Sword", 15, 0, 0)],
+            ..character("PlayerOne", CharacterType::Player, &world.game_map, 64, 64)
+        };
+        world.die(fallen.clone(), "slain by TrollOne".to_string());
+        assert_eq!(world.deaths[0].fate, Fate::Respawned(70, 70));
+
+        let player = world.characters().find(|character| character.name == "PlayerOne").expect("the player back");
+        assert_eq!((player.x_position, player.y_position, player.depth), (70, 70, 0));
+        assert_eq!(player.health, attributes::max_health(player).total / 2);
+        assert_eq!((player.energy, player.hydration), (RESTED / 2, RESTED / 2));
+        assert!(player.bag.is_empty() && player.effects.is_empty());
+        assert_eq!(player.progress, fallen.progress.setback(EXPERIENCE_PENALTY));
+        assert_ne!(player.progress, fallen.progress);
+        assert_eq!(player.progress.level(), fallen.progress.level());
+        assert!(player.explored.contains(0, 70, 70));
+    }
+
+    #[test]
+    fn a_death_says_what_happened() {
+        let event = DeathEvent {
+            name: "TrollOne".to_string(),
+            character_type: CharacterType::Troll,
+            cause: "slain by PlayerOne".to_string(),
+            x_position: 110,
+            y_position: 1512,
+            depth: 0,
+            level: 2,
+            items: 0,
+            fate: Fate::Returns(20),
+        };
+        assert_eq!(event.to_string(), "TrollOne the level 2 Troll died at (110, 1512) slain by PlayerOne, 0 items left on the corpse, back in 20 turns");
+        let gone = DeathEvent { depth: 2, fate: Fate::Gone, ..event };
+        assert!(gone.to_string().contains(" at depth 2 ") && gone.to_string().ends_with(", gone for good"));
+    }
+}
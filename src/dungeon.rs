@@ -0,0 +1,407 @@
+// Path: src/dungeon.rs
+
+use std::collections::{HashMap, VecDeque};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use crate::world_gen::WorldGenConfig;
+use crate::{GameMap, TerrainType, Tile};
+
+// ===========================================================================
+// Underground levels
+// below the island are levels of caves and dungeons, depth 1 is just under the surface.
+// odd depths are natural caves grown with a cellular automaton,
+// even depths are rooms and corridors laid out by splitting the level (BSP).
+// every level has stairs up to each set of stairs down on the level above,
+// and the overworld has cave entrances leading to the first level.
+
+// how much of a new cave starts out as rock
+const CAVE_FILL: f64 = 0.45;
+const CAVE_SMOOTHING: usize = 5;
+// BSP stops splitting below this size, rooms are at least MIN_ROOM across
+const MIN_LEAF: i32 = 14;
+const MIN_ROOM: i32 = 5;
+// stairs are kept at least this far apart
+const STAIR_SPACING: i32 = 8;
+
+// where each set of stairs leads, (depth, x, y) to (depth, x, y)
+pub type StairLinks = HashMap<(i32, i32, i32), (i32, i32, i32)>;
+// a corridor joins the centres of two rooms
+type Corridor = ((i32, i32), (i32, i32));
+
+// the layout of an underground level
+#[derive(Clone, Debug, PartialEq)]
+pub struct Level {
+    width: i32,
+    height: i32,
+    terrain: Vec<TerrainType>,
+}
+
+impl Level {
+    fn filled(width: i32, height: i32, terrain_type: TerrainType) -> Self {
+        Level {
+            width,
+            height,
+            terrain: vec![terrain_type; (width * height) as usize],
+        }
+    }
+
+    // anything off the level is solid rock
+    pub fn get(&self, x: i32, y: i32) -> TerrainType {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return TerrainType::Wall;
+        }
+        self.terrain[(y * self.width + x) as usize]
+    }
+
+    fn set(&mut self, x: i32, y: i32, terrain_type: TerrainType) {
+        if x > 0 && y > 0 && x < self.width - 1 && y < self.height - 1 {
+            self.terrain[(y * self.width + x) as usize] = terrain_type;
+        }
+    }
+
+    fn floors(&self) -> Vec<(i32, i32)> {
+        (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .filter(|&(x, y)| self.get(x, y) == TerrainType::Floor)
+            .collect()
+    }
+}
+
+// the overworld tiles that lead down to the first level
+pub fn place_entrances(map: &GameMap) -> HashMap<(i32, i32), Tile> {
+    let config = map.config();
+    let mut rng = StdRng::seed_from_u64((config.seed as u64) << 8 | 1);
+    let mut entrances: HashMap<(i32, i32), Tile> = HashMap::new();
+    if config.dungeon_levels < 1 {
+        return entrances;
+    }
+
+    // try random spots until enough of them are on dry inland ground
+    for _ in 0..10_000 {
+        if entrances.len() >= config.cave_entrances {
+            break;
+        }
+        let x = rng.gen_range(0..config.width);
+        let y = rng.gen_range(0..config.height);
+        let tile = map.generate_tile(x, y);
+        let crowded = entrances.keys().any(|(ex, ey)| (ex - x).abs() + (ey - y).abs() < STAIR_SPACING);
+        if tile.is_land() && tile.terrain_type != TerrainType::Beach && !crowded {
+            entrances.insert((x, y), Tile {
+                terrain_type: TerrainType::StairsDown,
+                ..tile
+            });
+        }
+    }
+    entrances
+}
+
+// build every level under the overworld, and the links between their stairs.
+// links go both ways, from (depth, x, y) to (depth, x, y)
+pub fn generate_underground(overworld: &GameMap) -> (Vec<GameMap>, StairLinks) {
+    let config = overworld.config();
+    let mut levels = Vec::new();
+    let mut links = HashMap::new();
+
+    // stairs down on the level above, sorted so the links do not depend on hash order
+    let mut above: Vec<(i32, i32)> = overworld.features.iter()
+        .filter(|(_, tile)| tile.terrain_type == TerrainType::StairsDown)
+        .map(|(position, _)| *position)
+        .collect();
+    above.sort();
+
+    for depth in 1..=config.dungeon_levels {
+        let mut rng = StdRng::seed_from_u64((config.seed as u64) << 8 | (depth as u64 + 1));
+        let mut level = if depth % 2 == 1 {
+            dig_caves(&mut rng, config.dungeon_width, config.dungeon_height)
+        } else {
+            build_rooms(&mut rng, config.dungeon_width, config.dungeon_height)
+        };
+
+        let down_count = if depth < config.dungeon_levels { config.cave_entrances } else { 0 };
+        let spots = pick_spots(&mut rng, &level, above.len() + down_count);
+        let (up, down) = spots.split_at(above.len().min(spots.len()));
+
+        for (&(from_x, from_y), &(x, y)) in above.iter().zip(up) {
+            level.set(x, y, TerrainType::StairsUp);
+            links.insert((depth - 1, from_x, from_y), (depth, x, y));
+            links.insert((depth, x, y), (depth - 1, from_x, from_y));
+        }
+        for &(x, y) in down {
+            level.set(x, y, TerrainType::StairsDown);
+        }
+        above = down.to_vec();
+
+        let level_config = WorldGenConfig {
+            width: config.dungeon_width,
+            height: config.dungeon_height,
+            ..config.clone()
+        };
+        levels.push(GameMap::underground(level_config, depth, level));
+    }
+    (levels, links)
+}
+
+// floor tiles for stairs, spread out over the level
+fn pick_spots(rng: &mut StdRng, level: &Level, count: usize) -> Vec<(i32, i32)> {
+    let mut floors = level.floors();
+    floors.shuffle(rng);
+    let mut spots: Vec<(i32, i32)> = Vec::new();
+    for (x, y) in floors {
+        if spots.len() >= count {
+            break;
+        }
+        if spots.iter().all(|(sx, sy)| (sx - x).abs() + (sy - y).abs() >= STAIR_SPACING) {
+            spots.push((x, y));
+        }
+    }
+    spots
+}
+
+// random rock, smoothed until it settles into caves, then only the biggest cave is kept
+fn dig_caves(rng: &mut StdRng, width: i32, height: i32) -> Level {
+    let mut level = Level::filled(width, height, TerrainType::Wall);
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            if !rng.gen_bool(CAVE_FILL) {
+                level.set(x, y, TerrainType::Floor);
+            }
+        }
+    }
+
+    for _ in 0..CAVE_SMOOTHING {
+        let mut next = level.clone();
+        for y in 1..height - 1 {
+            for x in 1..width - 1 {
+                let walls = (-1..=1)
+                    .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+                    .filter(|&(dx, dy)| (dx, dy) != (0, 0) && level.get(x + dx, y + dy) == TerrainType::Wall)
+                    .count();
+                // rock with enough rock around it stays, open ground closes in when surrounded
+                let terrain_type = if walls >= 5 || (walls >= 4 && level.get(x, y) == TerrainType::Wall) {
+                    TerrainType::Wall
+                } else {
+                    TerrainType::Floor
+                };
+                next.set(x, y, terrain_type);
+            }
+        }
+        level = next;
+    }
+
+    keep_largest_cave(&mut level);
+    level
+}
+
+// fill in every pocket of floor that can not be walked to from the biggest one
+fn keep_largest_cave(level: &mut Level) {
+    let mut region = vec![usize::MAX; level.terrain.len()];
+    let mut sizes = Vec::new();
+    for (x, y) in level.floors() {
+        let index = (y * level.width + x) as usize;
+        if region[index] != usize::MAX {
+            continue;
+        }
+        let id = sizes.len();
+        let mut size = 0;
+        let mut queue = VecDeque::from([(x, y)]);
+        region[index] = id;
+        while let Some((cx, cy)) = queue.pop_front() {
+            size += 1;
+            for (dx, dy) in [(0, -1), (1, 0), (0, 1), (-1, 0)] {
+                let (nx, ny) = (cx + dx, cy + dy);
+                let next = (ny * level.width + nx) as usize;
+                if level.get(nx, ny) == TerrainType::Floor && region[next] == usize::MAX {
+                    region[next] = id;
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+        sizes.push(size);
+    }
+
+    let largest = (0..sizes.len()).max_by_key(|&id| sizes[id]);
+    for (index, terrain_type) in level.terrain.iter_mut().enumerate() {
+        if *terrain_type == TerrainType::Floor && Some(region[index]) != largest {
+            *terrain_type = TerrainType::Wall;
+        }
+    }
+}
+
+// a rectangle of the level, x and y are the top left corner
+#[derive(Clone, Copy, Debug)]
+struct Rect {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+impl Rect {
+    fn center(&self) -> (i32, i32) {
+        (self.x + self.width / 2, self.y + self.height / 2)
+    }
+
+    fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+
+    // is x,y on the ring of wall just outside the room
+    fn on_edge(&self, x: i32, y: i32) -> bool {
+        let inside_x = x >= self.x && x < self.x + self.width;
+        let inside_y = y >= self.y && y < self.y + self.height;
+        (inside_x && (y == self.y - 1 || y == self.y + self.height))
+            || (inside_y && (x == self.x - 1 || x == self.x + self.width))
+    }
+}
+
+// split the level in two again and again, put a room in each piece,
+// and join every pair of pieces with a corridor
+fn build_rooms(rng: &mut StdRng, width: i32, height: i32) -> Level {
+    let mut level = Level::filled(width, height, TerrainType::Wall);
+    let mut rooms = Vec::new();
+    let mut corridors = Vec::new();
+    split(rng, Rect { x: 1, y: 1, width: width - 2, height: height - 2 }, &mut rooms, &mut corridors);
+
+    for room in &rooms {
+        for y in room.y..room.y + room.height {
+            for x in room.x..room.x + room.width {
+                level.set(x, y, TerrainType::Floor);
+            }
+        }
+    }
+    for ((from_x, from_y), (to_x, to_y)) in corridors {
+        // along then down, an L shaped corridor
+        let path: Vec<(i32, i32)> = walk(from_x, to_x).map(|x| (x, from_y))
+            .chain(walk(from_y, to_y).skip(1).map(|y| (to_x, y)))
+            .collect();
+        for (i, &(x, y)) in path.iter().enumerate() {
+            if level.get(x, y) != TerrainType::Wall {
+                continue;
+            }
+            // where the corridor steps through a room's wall into the room there is a door,
+            // a corridor running alongside the wall is just corridor
+            let neighbours = [i.checked_sub(1).map(|j| path[j]), path.get(i + 1).copied()];
+            let is_door = rooms.iter().any(|room| {
+                room.on_edge(x, y) && neighbours.iter().flatten().any(|&(nx, ny)| room.contains(nx, ny))
+            });
+            let terrain_type = if is_door { TerrainType::Door } else { TerrainType::Floor };
+            level.set(x, y, terrain_type);
+        }
+    }
+    level
+}
+
+// every step from one position to another, in order, both ends included
+fn walk(from: i32, to: i32) -> Box<dyn Iterator<Item = i32>> {
+    if from <= to { Box::new(from..=to) } else { Box::new((to..=from).rev()) }
+}
+
+// returns the room picked to represent this part of the level
+fn split(rng: &mut StdRng, area: Rect, rooms: &mut Vec<Rect>, corridors: &mut Vec<Corridor>) -> Rect {
+    let can_split_x = area.width >= MIN_LEAF * 2;
+    let can_split_y = area.height >= MIN_LEAF * 2;
+
+    if !can_split_x && !can_split_y {
+        // leave a wall between the room and the edge of its area
+        let room_width = rng.gen_range(MIN_ROOM..=(area.width - 2).max(MIN_ROOM));
+        let room_height = rng.gen_range(MIN_ROOM..=(area.height - 2).max(MIN_ROOM));
+        let room = Rect {
+            x: area.x + rng.gen_range(1..=(area.width - room_width - 1).max(1)),
+            y: area.y + rng.gen_range(1..=(area.height - room_height - 1).max(1)),
+            width: room_width,
+            height: room_height,
+        };
+        rooms.push(room);
+        return room;
+    }
+
+    // cut across the longer side
+    let split_x = can_split_x && (!can_split_y || area.width >= area.height);
+    let (first, second) = if split_x {
+        let cut = rng.gen_range(MIN_LEAF..=area.width - MIN_LEAF);
+        (Rect { width: cut, ..area }, Rect { x: area.x + cut, width: area.width - cut, ..area })
+    } else {
+        let cut = rng.gen_range(MIN_LEAF..=area.height - MIN_LEAF);
+        (Rect { height: cut, ..area }, Rect { y: area.y + cut, height: area.height - cut, ..area })
+    };
+    let first_room = split(rng, first, rooms, corridors);
+    let second_room = split(rng, second, rooms, corridors);
+    corridors.push((first_room.center(), second_room.center()));
+    if rng.gen_bool(0.5) { first_room } else { second_room }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::GameObject;
+
+    fn world(seed: u32) -> (GameMap, Vec<GameMap>, StairLinks) {
+        let config = WorldGenConfig { width: 256, height: 256, falloff_margin: 80.0, dungeon_levels: 4, ..WorldGenConfig::with_seed(seed) };
+        let overworld = GameMap::new(config);
+        let (levels, links) = generate_underground(&overworld);
+        (overworld, levels, links)
+    }
+
+    fn terrain(overworld: &GameMap, levels: &[GameMap], depth: i32, x: i32, y: i32) -> TerrainType {
+        let map = if depth == 0 { overworld } else { &levels[depth as usize - 1] };
+        map.get_tile(x, y).terrain_type
+    }
+
+    #[test]
+    fn every_level_is_one_connected_space() {
+        for seed in [1, 2, 3] {
+            let (_, levels, _) = world(seed);
+            for map in &levels {
+                let config = map.config();
+                let open: HashSet<(i32, i32)> = (0..config.height)
+                    .flat_map(|y| (0..config.width).map(move |x| (x, y)))
+                    .filter(|&(x, y)| map.get_tile(x, y).terrain_type != TerrainType::Wall)
+                    .collect();
+                let start = *open.iter().min().expect("a level with no floor");
+                let mut reached = HashSet::from([start]);
+                let mut queue = VecDeque::from([start]);
+                while let Some((x, y)) = queue.pop_front() {
+                    for (dx, dy) in [(0, -1), (1, 0), (0, 1), (-1, 0)] {
+                        let next = (x + dx, y + dy);
+                        if open.contains(&next) && reached.insert(next) {
+                            queue.push_back(next);
+                        }
+                    }
+                }
+                assert_eq!(reached.len(), open.len(), "depth {} of seed {} is split", map.depth(), seed);
+            }
+        }
+    }
+
+    #[test]
+    fn every_stair_leads_to_a_stair_next_door_and_back() {
+        for seed in [1, 2, 3] {
+            let (overworld, levels, links) = world(seed);
+            assert!(!links.is_empty());
+            for (&(depth, x, y), &(to_depth, to_x, to_y)) in &links {
+                assert_eq!((to_depth - depth).abs(), 1);
+                let (here, there) = if to_depth > depth {
+                    (TerrainType::StairsDown, TerrainType::StairsUp)
+                } else {
+                    (TerrainType::StairsUp, TerrainType::StairsDown)
+                };
+                assert_eq!(terrain(&overworld, &levels, depth, x, y), here);
+                assert_eq!(terrain(&overworld, &levels, to_depth, to_x, to_y), there);
+                assert_eq!(links.get(&(to_depth, to_x, to_y)), Some(&(depth, x, y)));
+            }
+            // and no stairs go nowhere
+            for map in &levels {
+                let config = map.config();
+                for (x, y) in (0..config.height).flat_map(|y| (0..config.width).map(move |x| (x, y))) {
+                    if matches!(map.get_tile(x, y).terrain_type, TerrainType::StairsUp | TerrainType::StairsDown) {
+                        assert!(links.contains_key(&(map.depth(), x, y)), "stairs at depth {} ({}, {}) lead nowhere", map.depth(), x, y);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,215 @@
+// Path: src/fov.rs
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::{Color, GameMap, GameObject, TerrainType};
+
+// ===========================================================================
+// Field of view
+// a character sees the tiles around it that are not hidden behind something,
+// worked out by shadowcasting, one eighth of the circle at a time.
+// walls and mountains block sight, and so does ground well above the character's head,
+// while standing on high ground lets a character see farther.
+// every tile a character has seen is remembered, one bit per tile for each level.
+
+// how far a character sees on flat ground at the shore
+const BASE_SIGHT: i32 = 12;
+// each this much height above the sea adds one tile of sight
+const HEIGHT_PER_SIGHT: i32 = 2;
+const MAX_SIGHT: i32 = 40;
+// ground this much higher than the character hides what is behind it
+const RIDGE_HEIGHT: i32 = 6;
+
+// the eighths of the circle, as the ways a row and a column step across the map
+const OCTANTS: [(i32, i32, i32, i32); 8] = [
+    (1, 0, 0, 1), (0, 1, 1, 0), (0, -1, 1, 0), (-1, 0, 0, 1),
+    (-1, 0, 0, -1), (0, -1, -1, 0), (0, 1, -1, 0), (1, 0, 0, -1),
+];
+
+// how much of a tile a character knows about
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Seen {
+    // never seen
+    Unexplored,
+    // seen before but not in sight now
+    Remembered,
+    // in sight now
+    Visible,
+}
+
+impl Seen {
+    // the colour to draw a tile in, None when nothing should be drawn
+    pub fn shade(&self, color: Color) -> Option<Color> {
+        match self {
+            Seen::Unexplored => None,
+            Seen::Remembered => Some(color.darker()),
+            Seen::Visible => Some(color),
+        }
+    }
+}
+
+// one bit for every tile of a level
+#[derive(Clone, PartialEq)]
+struct Bitset {
+    width: i32,
+    height: i32,
+    bits: Vec<u64>,
+}
+
+impl Bitset {
+    fn new(width: i32, height: i32) -> Self {
+        let count = (width as usize * height as usize).div_ceil(64);
+        Bitset { width, height, bits: vec![0; count] }
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return None;
+        }
+        Some((y * self.width + x) as usize)
+    }
+
+    fn set(&mut self, x: i32, y: i32) {
+        if let Some(index) = self.index(x, y) {
+            self.bits[index / 64] |= 1 << (index % 64);
+        }
+    }
+
+    fn get(&self, x: i32, y: i32) -> bool {
+        self.index(x, y).is_some_and(|index| self.bits[index / 64] & (1 << (index % 64)) != 0)
+    }
+
+    fn count(&self) -> u32 {
+        self.bits.iter().map(|word| word.count_ones()).sum()
+    }
+}
+
+// the bits themselves are far too many to print
+impl fmt::Debug for Bitset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} of {}x{} tiles", self.count(), self.width, self.height)
+    }
+}
+
+// the tiles a character has seen, on every level it has been to
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Explored {
+    levels: HashMap<i32, Bitset>,
+}
+
+impl Explored {
+    pub fn contains(&self, depth: i32, x: i32, y: i32) -> bool {
+        self.levels.get(&depth).is_some_and(|level| level.get(x, y))
+    }
+
+    // remember the tiles in sight on a map
+    pub fn mark(&mut self, map: &GameMap, visible: &HashSet<(i32, i32)>) {
+        let config = map.config();
+        let level = self.levels.entry(map.depth())
+            .or_insert_with(|| Bitset::new(config.width, config.height));
+        for &(x, y) in visible {
+            level.set(x, y);
+        }
+    }
+}
+
+// how far a character standing at x,y can see
+pub fn sight_range(map: &GameMap, x: i32, y: i32) -> i32 {
+    let elevation = map.get_tile(x, y).elevation.max(0);
+    (BASE_SIGHT + elevation / HEIGHT_PER_SIGHT).min(MAX_SIGHT)
+}
+
+// the tiles that can be seen from x,y
+pub fn field_of_view(map: &GameMap, x: i32, y: i32) -> HashSet<(i32, i32)> {
+    let radius = sight_range(map, x, y);
+    let eye = map.get_tile(x, y).elevation;
+    let config = map.config();
+    let blocks = |tx: i32, ty: i32| {
+        let tile = map.get_tile(tx, ty);
+        match tile.terrain_type {
+            TerrainType::Wall | TerrainType::Boundary => true,
+            TerrainType::Mountain => tile.elevation > eye,
+            _ => tile.elevation > eye + RIDGE_HEIGHT,
+        }
+    };
+
+    let mut visible = HashSet::from([(x, y)]);
+    for octant in OCTANTS {
+        cast(&mut visible, &blocks, (x, y), radius, 1, 1.0, 0.0, octant);
+    }
+    // on a wrap-around world the view can run over the side of the map
+    visible.into_iter()
+        .map(|(vx, vy)| (config.wrap_x(vx), vy))
+        .filter(|&(vx, vy)| config.in_bounds(vx, vy))
+        .collect()
+}
+
+// light up one octant from row outwards, between the start and end slopes,
+// each blocking tile casts a shadow over the rows beyond it
+#[allow(clippy::too_many_arguments)]
+fn cast<F>(visible: &mut HashSet<(i32, i32)>, blocks: &F, (x, y): (i32, i32), radius: i32,
+           row: i32, mut start: f64, end: f64, (xx, xy, yx, yy): (i32, i32, i32, i32))
+    where F: Fn(i32, i32) -> bool
+{
+    if start < end {
+        return;
+    }
+    let mut next_start = start;
+    for distance in row..=radius {
+        let mut blocked = false;
+        let dy = -distance;
+        for dx in -distance..=0 {
+            let left = (dx as f64 - 0.5) / (dy as f64 + 0.5);
+            let right = (dx as f64 + 0.5) / (dy as f64 - 0.5);
+            if start < right {
+                continue;
+            }
+            if end > left {
+                break;
+            }
+            let (tx, ty) = (x + dx * xx + dy * xy, y + dx * yx + dy * yy);
+            if dx * dx + dy * dy <= radius * radius {
+                visible.insert((tx, ty));
+            }
+            if blocked {
+                if blocks(tx, ty) {
+                    next_start = right;
+                } else {
+                    blocked = false;
+                    start = next_start;
+                }
+            } else if blocks(tx, ty) && distance < radius {
+                blocked = true;
+                cast(visible, blocks, (x, y), radius, distance + 1, start, left, (xx, xy, yx, yy));
+                next_start = right;
+            }
+        }
+        if blocked {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world_gen::WorldGenConfig;
+
+    #[test]
+    fn mountains_hide_what_is_behind_them() {
+        let map = GameMap::new(WorldGenConfig { width: 256, height: 256, falloff_margin: 80.0, ..WorldGenConfig::with_seed(4) });
+        // a character just below a mountain, looking straight across it
+        let (x, y, dx) = (0..256).flat_map(|y| (1..255).map(move |x| (x, y)))
+            .flat_map(|(x, y)| [(x, y, 1), (x, y, -1)])
+            .find(|&(x, y, dx)| {
+                let (here, mountain) = (map.get_tile(x, y), map.get_tile(x + dx, y));
+                here.is_land() && here.terrain_type != TerrainType::Mountain
+                    && mountain.terrain_type == TerrainType::Mountain && mountain.elevation > here.elevation
+            })
+            .expect("land at the foot of a mountain");
+        let visible = field_of_view(&map, x, y);
+        assert!(visible.contains(&(x + dx, y)));
+        assert!(!visible.contains(&(x + 2 * dx, y)));
+    }
+}
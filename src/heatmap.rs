@@ -0,0 +1,175 @@
+// Path: src/heatmap.rs
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use image::ImageResult;
+
+use crate::relief;
+use crate::{Color, World};
+
+// ===========================================================================
+// Heatmaps
+// counts of what happened on each tile, such as footsteps or deaths,
+// drawn as a colour ramp over the terrain so clusters stand out.
+// tiles are gathered into square cells first, a single tile is too small to see on the map.
+// the raw counts for every tile are saved next to the image as csv.
+
+// how many of something happened on each tile of the overworld
+pub type Counts = HashMap<(i32, i32), u32>;
+
+// the most a cell covers the terrain under it, 0-1
+const MAX_OPACITY: f64 = 0.85;
+pub const DEFAULT_CELL: i32 = 8;
+
+// everything the world keeps count of
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Tallies {
+    // a step taken onto the tile
+    pub footsteps: Counts,
+    // an attack made from the tile
+    pub combat: Counts,
+    // a character died on the tile
+    pub deaths: Counts,
+}
+
+pub fn add(counts: &mut Counts, x: i32, y: i32) {
+    *counts.entry((x, y)).or_insert(0) += 1;
+}
+
+// what a heatmap shows
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HeatLayer {
+    Items,
+    Footsteps,
+    Combat,
+    Deaths,
+}
+
+impl FromStr for HeatLayer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "items" => Ok(HeatLayer::Items),
+            "footsteps" => Ok(HeatLayer::Footsteps),
+            "combat" => Ok(HeatLayer::Combat),
+            "deaths" => Ok(HeatLayer::Deaths),
+            _ => Err(format!("Unknown heatmap: {}", s)),
+        }
+    }
+}
+
+// the colours from the fewest to the most
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ramp {
+    // dark red through orange and yellow to white
+    Heat,
+    // blue through green to yellow
+    Cool,
+    // black to white
+    Mono,
+}
+
+impl Ramp {
+    fn stops(&self) -> &'static [(f64, Color)] {
+        const HEAT: [(f64, Color); 4] = [
+            (0.0, Color::MAROON),
+            (0.4, Color::RED),
+            (0.75, Color::YELLOW),
+            (1.0, Color::WHITE),
+        ];
+        const COOL: [(f64, Color); 3] = [
+            (0.0, Color::NAVY),
+            (0.5, Color::TEAL),
+            (1.0, Color::YELLOW),
+        ];
+        const MONO: [(f64, Color); 2] = [
+            (0.0, Color::BLACK),
+            (1.0, Color::WHITE),
+        ];
+        match self {
+            Ramp::Heat => &HEAT,
+            Ramp::Cool => &COOL,
+            Ramp::Mono => &MONO,
+        }
+    }
+}
+
+impl FromStr for Ramp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "heat" => Ok(Ramp::Heat),
+            "cool" => Ok(Ramp::Cool),
+            "mono" => Ok(Ramp::Mono),
+            _ => Err(format!("Unknown colour ramp: {}", s)),
+        }
+    }
+}
+
+// the counts a heatmap is drawn from
+pub fn counts(world: &World, layer: HeatLayer) -> Counts {
+    match layer {
+        HeatLayer::Items => {
+            let mut counts = Counts::new();
+            for (x, y) in world.items_by_position.keys() {
+                add(&mut counts, *x, *y);
+            }
+            counts
+        }
+        HeatLayer::Footsteps => world.tallies.footsteps.clone(),
+        HeatLayer::Combat => world.tallies.combat.clone(),
+        HeatLayer::Deaths => world.tallies.deaths.clone(),
+    }
+}
+
+// draw the counts over the terrain, cell tiles to a side, and save them as filename,
+// with the counts for each tile in a csv file of the same name
+pub fn export(world: &World, counts: &Counts, filename: &str, ramp: Ramp, cell: i32) -> ImageResult<()> {
+    let cell = cell.max(1);
+    let mut cells = Counts::new();
+    for (&(x, y), &count) in counts {
+        *cells.entry((x / cell, y / cell)).or_insert(0) += count;
+    }
+    // the busiest cell gets the top of the ramp, a square root keeps quieter cells from vanishing
+    let most = cells.values().copied().max().unwrap_or(1) as f64;
+
+    let mut img = world.game_map.terrain_image();
+    for (&(cx, cy), &count) in &cells {
+        let share = (count as f64 / most).sqrt();
+        let heat = relief::ramp(ramp.stops(), share);
+        let opacity = MAX_OPACITY * share.max(0.2);
+        for y in cy * cell..(cy + 1) * cell {
+            for x in cx * cell..(cx + 1) * cell {
+                if x < 0 || y < 0 || x >= img.width() as i32 || y >= img.height() as i32 {
+                    continue;
+                }
+                let pixel = img.get_pixel_mut(x as u32, y as u32);
+                let under = Color { r: pixel[0], g: pixel[1], b: pixel[2] };
+                *pixel = blend(under, heat, opacity).to_rgb();
+            }
+        }
+    }
+    img.save(filename)?;
+    fs::write(Path::new(filename).with_extension("csv"), csv(counts))?;
+    Ok(())
+}
+
+// one line for each tile that has a count, in order of position
+fn csv(counts: &Counts) -> String {
+    let mut tiles: Vec<(&(i32, i32), &u32)> = counts.iter().collect();
+    tiles.sort();
+    let mut text = String::from("x,y,count\n");
+    for ((x, y), count) in tiles {
+        text.push_str(&format!("{},{},{}\n", x, y, count));
+    }
+    text
+}
+
+fn blend(under: Color, over: Color, opacity: f64) -> Color {
+    let mix = |a: u8, b: u8| (a as f64 * (1.0 - opacity) + b as f64 * opacity).round() as u8;
+    Color { r: mix(under.r, over.r), g: mix(under.g, over.g), b: mix(under.b, over.b) }
+}
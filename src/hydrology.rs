@@ -0,0 +1,243 @@
+// Path: src/hydrology.rs
+
+use std::cmp::{max, Ordering};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use noise::NoiseFn;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::{GameMap, TerrainType, Tile};
+
+// ===========================================================================
+// Rivers and lakes
+// rivers start on the highest ground and run down the steepest slope to the sea.
+// a river that gets stuck in a hollow fills it up into a lake, then carries on
+// from the lowest point of the lake's rim.
+// rivers that meet share the rest of the way down, so the river below a
+// junction carries more water and gets wider and deeper.
+
+// how many random spots are looked at when choosing river sources
+const SOURCE_SAMPLES: usize = 4000;
+// sources closer than this would only make parallel rivers
+const SOURCE_SPACING: i32 = 96;
+// a river that has not reached the sea after this many steps gives up
+const MAX_RIVER_LENGTH: usize = 8000;
+// water this deep or shallower can be waded through
+pub const FORDABLE_DEPTH: i32 = 1;
+// rivers follow the land plus a little roughness, so they bend instead of running straight
+const MEANDER_HEIGHT: f64 = 0.5;
+const MEANDER_SCALE: f64 = 40.0;
+
+const NEIGHBORS: [(i32, i32); 8] = [(0, -1), (1, -1), (1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1)];
+
+// a cell waiting in the lake flood, the lowest cell comes out of the heap first
+struct FloodCell {
+    elevation: f64,
+    x: i32,
+    y: i32,
+}
+
+impl PartialEq for FloodCell {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for FloodCell {}
+
+impl PartialOrd for FloodCell {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for FloodCell {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.elevation.total_cmp(&self.elevation)
+            .then_with(|| (other.y, other.x).cmp(&(self.y, self.x)))
+    }
+}
+
+// work out the river and lake tiles for a map, keyed by position
+pub fn generate(map: &GameMap) -> HashMap<(i32, i32), Tile> {
+    let mut features = HashMap::new();
+    if map.config().river_count == 0 {
+        return features;
+    }
+
+    // how many rivers pass through each tile, and how deep each lake tile is
+    let mut flow: HashMap<(i32, i32), i32> = HashMap::new();
+    let mut lakes: HashMap<(i32, i32), i32> = HashMap::new();
+    for source in find_sources(map) {
+        trace_river(map, source, &mut flow, &mut lakes);
+    }
+
+    for (&(x, y), &depth) in &lakes {
+        paint(map, &mut features, x, y, TerrainType::Lake, depth);
+    }
+    for (&(x, y), &amount) in &flow {
+        // one river is a shallow stream, every couple of tributaries make it wider and deeper
+        let radius = match amount {
+            1 => 0,
+            2..=3 => 1,
+            _ => 2,
+        };
+        let depth = FORDABLE_DEPTH + radius;
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                if dx * dx + dy * dy > radius * radius {
+                    continue;
+                }
+                // the banks are shallower than the middle of the river
+                let bank_depth = if dx == 0 && dy == 0 { depth } else { max(FORDABLE_DEPTH, depth - 1) };
+                paint(map, &mut features, map.config().wrap_x(x + dx), y + dy, TerrainType::River, bank_depth);
+            }
+        }
+    }
+    features
+}
+
+// sources are on mountains, or on the highest land when a world has no mountains
+fn find_sources(map: &GameMap) -> Vec<(i32, i32)> {
+    let config = map.config();
+    let mut rng = StdRng::seed_from_u64(config.seed as u64);
+
+    let mut candidates: Vec<(bool, f64, i32, i32)> = (0..SOURCE_SAMPLES)
+        .filter_map(|_| {
+            let x = rng.gen_range(0..config.width);
+            let y = rng.gen_range(0..config.height);
+            let tile = map.generate_tile(x, y);
+            if !tile.is_land() || tile.terrain_type == TerrainType::Beach {
+                return None;
+            }
+            let is_mountain = tile.elevation >= config.mountain_threshold;
+            Some((is_mountain, water_level(map, x, y), x, y))
+        })
+        .collect();
+    candidates.sort_by(|a, b| b.0.cmp(&a.0).then(b.1.total_cmp(&a.1)));
+
+    let mut sources: Vec<(i32, i32)> = Vec::new();
+    for (_, _, x, y) in candidates {
+        if sources.len() >= config.river_count {
+            break;
+        }
+        let crowded = sources.iter().any(|(sx, sy)| (sx - x).abs() < SOURCE_SPACING && (sy - y).abs() < SOURCE_SPACING);
+        if !crowded {
+            sources.push((x, y));
+        }
+    }
+    sources
+}
+
+// follow the steepest way down from the source until the river reaches the sea
+fn trace_river(map: &GameMap, source: (i32, i32), flow: &mut HashMap<(i32, i32), i32>, lakes: &mut HashMap<(i32, i32), i32>) {
+    let (mut x, mut y) = source;
+    let mut visited: HashSet<(i32, i32)> = HashSet::new();
+
+    for _ in 0..MAX_RIVER_LENGTH {
+        if is_sea(map, x, y) {
+            return;
+        }
+        *flow.entry((x, y)).or_insert(0) += 1;
+        visited.insert((x, y));
+
+        let here = water_level(map, x, y);
+        let lowest = NEIGHBORS.iter()
+            .map(|(dx, dy)| (map.config().wrap_x(x + dx), y + dy))
+            .filter(|position| !visited.contains(position))
+            .map(|(nx, ny)| (water_level(map, nx, ny), nx, ny))
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+
+        match lowest {
+            Some((elevation, nx, ny)) if elevation < here => {
+                x = nx;
+                y = ny;
+            }
+            // stuck in a hollow
+            _ => match fill_lake(map, x, y, &mut visited, lakes) {
+                Some((spill_x, spill_y)) => {
+                    x = spill_x;
+                    y = spill_y;
+                }
+                None => return,
+            },
+        }
+    }
+}
+
+// flood the hollow at x,y from the lowest rim cell upwards until the water
+// finds a way out, returns where the water spills over
+fn fill_lake(map: &GameMap, x: i32, y: i32, visited: &mut HashSet<(i32, i32)>, lakes: &mut HashMap<(i32, i32), i32>) -> Option<(i32, i32)> {
+    let max_lake_size = map.config().max_lake_size;
+    let mut level = water_level(map, x, y);
+    let mut heap = BinaryHeap::new();
+    let mut seen: HashSet<(i32, i32)> = HashSet::new();
+    let mut flooded: Vec<(i32, i32, f64)> = Vec::new();
+    heap.push(FloodCell { elevation: level, x, y });
+    seen.insert((x, y));
+
+    let mut spill = None;
+    while let Some(cell) = heap.pop() {
+        // lower than the water, so this is where the lake drains,
+        // unless the river has already been there, then it is part of the lake
+        let is_new = !visited.contains(&(cell.x, cell.y));
+        if (is_new && cell.elevation < level) || is_sea(map, cell.x, cell.y) {
+            spill = Some((cell.x, cell.y));
+            break;
+        }
+        level = level.max(cell.elevation);
+        flooded.push((cell.x, cell.y, cell.elevation));
+        if flooded.len() >= max_lake_size {
+            // the river ends in a big lake with no way out
+            break;
+        }
+        for (dx, dy) in NEIGHBORS {
+            let (nx, ny) = (map.config().wrap_x(cell.x + dx), cell.y + dy);
+            if map.config().in_bounds(nx, ny) && seen.insert((nx, ny)) {
+                heap.push(FloodCell { elevation: water_level(map, nx, ny), x: nx, y: ny });
+            }
+        }
+    }
+
+    for (lake_x, lake_y, elevation) in flooded {
+        let depth = max(FORDABLE_DEPTH, (level - elevation).ceil() as i32);
+        let entry = lakes.entry((lake_x, lake_y)).or_insert(depth);
+        *entry = max(*entry, depth);
+        visited.insert((lake_x, lake_y));
+    }
+    spill
+}
+
+// the height water sees when it runs over x,y
+fn water_level(map: &GameMap, x: i32, y: i32) -> f64 {
+    let roughness = map.noise.meander.get([x as f64 / MEANDER_SCALE, y as f64 / MEANDER_SCALE]);
+    map.elevation_at(x, y) + roughness * MEANDER_HEIGHT
+}
+
+// below sea level or off the edge, checked on the raw height as building a whole tile is slow
+pub fn is_sea(map: &GameMap, x: i32, y: i32) -> bool {
+    let config = map.config();
+    let x = config.wrap_x(x);
+    !config.in_bounds(x, y) || config.is_boundary(x, y) || map.elevation_at(x, y) < 1.0
+}
+
+// turn the tile at x,y into river or lake, keeping the deepest water when
+// features overlap so the result does not depend on the order they are painted
+fn paint(map: &GameMap, features: &mut HashMap<(i32, i32), Tile>, x: i32, y: i32, terrain_type: TerrainType, depth: i32) {
+    let tile = match features.get(&(x, y)) {
+        Some(tile) => tile.clone(),
+        None => {
+            let tile = map.generate_tile(x, y);
+            if !tile.is_land() {
+                return;
+            }
+            tile
+        }
+    };
+    let terrain_type = if tile.terrain_type == TerrainType::Lake { TerrainType::Lake } else { terrain_type };
+    features.insert((x, y), Tile {
+        terrain_type,
+        depth: max(tile.depth, depth),
+        ..tile
+    });
+}
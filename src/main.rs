@@ -5,16 +5,59 @@ use std::io;
 use std::io::Write;
 use std::thread;
 use std::cmp::max;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::time::Duration;
 use std::fmt;
-use noise::{NoiseFn, Perlin};
-use std::mem::needs_drop;
-use image::{ImageBuffer, Rgb};
+use std::sync::Arc;
+use std::str::FromStr;
+use image::{ImageBuffer, Rgb, RgbImage};
 use rayon::prelude::*;
-use once_cell::sync::Lazy;
-use std::sync::Mutex;
 use rand::Rng;
+use rand::seq::SliceRandom;
+
+mod attributes;
+mod biome;
+mod combat;
+mod death;
+mod dungeon;
+mod erosion;
+mod fov;
+mod heatmap;
+mod hydrology;
+mod map_export;
+mod naming;
+mod palette;
+mod progress;
+mod recorder;
+mod regions;
+mod relief;
+mod settlement;
+mod shape;
+mod sprites;
+mod status;
+mod svg_export;
+mod tile_cache;
+mod tile_pyramid;
+mod viewport;
+mod world_gen;
+mod zones;
+
+use attributes::{Attribute, Attributes};
+use death::{Corpse, DeathEvent, Fate, Respawn};
+use dungeon::{Level, StairLinks};
+use erosion::ElevationGrid;
+use fov::{Explored, Seen};
+use heatmap::{HeatLayer, Ramp, Tallies};
+use progress::{Progress, Skill};
+use map_export::MapLayers;
+use recorder::Recorder;
+use regions::Regions;
+use relief::MapStyle;
+use settlement::Settlement;
+use status::{EffectKind, StatusEffect};
+use tile_cache::{CacheStats, TileCache};
+use world_gen::{WorldGenConfig, WorldNoise};
+use zones::{SpawnKind, Zone};
 
 // this is attempt at designing a simple role playing game in Rust
 
@@ -45,7 +88,7 @@ use rand::Rng;
 // a character is facing in one of the eight directions.
 
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Direction {
     North,
     South,
@@ -102,6 +145,20 @@ impl Direction {
         }
     }
 
+    // an arrow pointing the way the direction faces
+    pub fn arrow(&self) -> char {
+        match self {
+            Direction::North => '↑',
+            Direction::South => '↓',
+            Direction::East => '→',
+            Direction::West => '←',
+            Direction::NorthEast => '↗',
+            Direction::NorthWest => '↖',
+            Direction::SouthEast => '↘',
+            Direction::SouthWest => '↙',
+        }
+    }
+
     // Get the opposite direction
     pub fn opposite(&self) -> Direction {
         match self {
@@ -144,21 +201,6 @@ impl Direction {
         }
     }
 
-    // get the direction from a string
-    pub fn from_str(s: &str) -> Option<Direction> {
-        match s {
-            "North" => Some(Direction::North),
-            "South" => Some(Direction::South),
-            "East" => Some(Direction::East),
-            "West" => Some(Direction::West),
-            "NorthEast" => Some(Direction::NorthEast),
-            "NorthWest" => Some(Direction::NorthWest),
-            "SouthEast" => Some(Direction::SouthEast),
-            "SouthWest" => Some(Direction::SouthWest),
-            _ => None,
-        }
-    }
-
     pub fn from_lower_case_str(s: &str) -> Option<Direction> {
         match s {
             "north" => Some(Direction::North),
@@ -190,6 +232,25 @@ impl Direction {
     }
 }
 
+// get the direction from a string
+impl FromStr for Direction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "North" => Ok(Direction::North),
+            "South" => Ok(Direction::South),
+            "East" => Ok(Direction::East),
+            "West" => Ok(Direction::West),
+            "NorthEast" => Ok(Direction::NorthEast),
+            "NorthWest" => Ok(Direction::NorthWest),
+            "SouthEast" => Ok(Direction::SouthEast),
+            "SouthWest" => Ok(Direction::SouthWest),
+            _ => Err(format!("Unknown direction: {}", s)),
+        }
+    }
+}
+
 
 // implement the commands
 pub enum Command {
@@ -200,24 +261,43 @@ pub enum Command {
     Look(String),
     Move,
     MoveTo(i32, i32),
-    Attack,
+    // strike at the named character standing next to the player
+    Attack(String),
     Defend,
     AddItem(Item),
+    // eat, drink or otherwise use the named item from the player's bag
+    Use(String),
+    // spend a point from levelling on an attribute
+    Train(Attribute),
     Me,
     See(String),
+    Stats,
+    Descend,
+    Climb,
+    Towns,
+    // draw the map around the player, width by height tiles, only what the player has seen when fogged
+    Map(i32, i32, bool),
+    // save the map image with the chosen layers and style to a file, as the named character sees it if any
+    Export(String, MapLayers, MapStyle, Option<String>),
+    // save the map as a pyramid of zoomable images into a directory
+    Tiles(String),
+    // start recording, or stop and save the recording to a gif or a directory of frames
+    Record(Option<String>),
+    // save a heatmap of items, footsteps, combat or deaths, in a colour ramp, cell tiles to a side
+    Heatmap(HeatLayer, String, Ramp, i32),
+    // save the coast, contours every so high, characters and items as an svg, sampling every so many tiles
+    Svg(String, f64, i32),
+    // list the themes, or draw everything in the named theme, from a palette file if one is given
+    Theme(Option<String>, Option<String>),
+    // save a picture of width by height tiles around the player, drawn with the sprites in a mapping file
+    Screenshot(String, String, i32, i32),
 
 }
 
 
-// the main Perlin noise generator
-static PERLIN: Lazy<Perlin> = Lazy::new(|| {
-    Perlin::new(7243)
-});
-
-
 // things are drawn on the map in different colours
 // colours by name
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
@@ -241,12 +321,39 @@ impl Color {
     pub const NAVY: Self = Self { r: 0, g: 0, b: 128 };
     pub const FUCHSIA: Self = Self { r: 255, g: 0, b: 255 };
     pub const PURPLE: Self = Self { r: 128, g: 0, b: 128 };
+    pub const DARK_GREEN: Self = Self { r: 0, g: 100, b: 0 };
+    pub const SEA_GREEN: Self = Self { r: 46, g: 139, b: 87 };
+    pub const DARK_OLIVE: Self = Self { r: 85, g: 107, b: 47 };
+    pub const DARK_BROWN: Self = Self { r: 139, g: 69, b: 19 };
+    pub const SAND: Self = Self { r: 237, g: 201, b: 175 };
+    pub const SNOW: Self = Self { r: 240, g: 248, b: 255 };
+    pub const DODGER_BLUE: Self = Self { r: 30, g: 144, b: 255 };
 }
 
 impl Color {
     pub fn to_rgb(&self) -> image::Rgb<u8> {
         image::Rgb([self.r, self.g, self.b])
     }
+
+    // #rrggbb, as used in svg and html
+    pub fn to_hex(&self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let digits = hex.strip_prefix('#')?;
+        // from_str_radix would take a sign as well as digits
+        if digits.len() != 6 || !digits.bytes().all(|digit| digit.is_ascii_hexdigit()) {
+            return None;
+        }
+        let channel = |at: usize| u8::from_str_radix(&digits[at..at + 2], 16).ok();
+        Some(Self { r: channel(0)?, g: channel(2)?, b: channel(4)? })
+    }
+
+    // the escape code that writes text in this colour on a 24 bit colour terminal
+    pub fn to_ansi(&self) -> String {
+        format!("\x1b[38;2;{};{};{}m", self.r, self.g, self.b)
+    }
     pub fn lighter(&self) -> Self {
         Self {
             r: self.r.saturating_add(64),
@@ -278,42 +385,67 @@ pub struct Tile {
     x_position: i32,
     y_position: i32,
     elevation: i32,
+    // climate on a 0-100 scale, decides the biome
+    moisture: i32,
+    temperature: i32,
+    // how deep the water is on rivers and lakes, 0 everywhere else
+    depth: i32,
+    // whether fighting is allowed and monsters roam here
+    zone: Zone,
+    // what appears here, if anything
+    spawn: Option<SpawnKind>,
     // other tile properties...
 }
 
 // implement tile
 impl Tile {
     // tile is generated by an algorithm, based on its position in the world.
-    pub fn new(x: i32, y: i32, width: i32, height: i32) -> Self {
+    // the same config and noise always produce the same tile.
+    pub fn new(x: i32, y: i32, config: &WorldGenConfig, noise: &WorldNoise) -> Self {
+        Self::from_elevation(x, y, config, noise, &|x, y| Self::calc_elevation(config, &noise.elevation, x, y))
+    }
+
+    // tile for a given height field, such as the noise after erosion has worn it down
+    pub fn from_elevation(x: i32, y: i32, config: &WorldGenConfig, noise: &WorldNoise, height: &dyn Fn(f64, f64) -> f64) -> Self {
         let mut is_beach = false;
 
         // if x and y are off the map, return limbo
-        if x < 0 || y < 0 || x >= width || y >= height {
+        if !config.in_bounds(x, y) {
             return Tile {
                 terrain_type: TerrainType::Limbo,
                 x_position: x,
                 y_position: y,
                 elevation: 0,
+                moisture: 0,
+                temperature: 0,
+                depth: 0,
+                zone: Zone::Wild,
+                spawn: None,
             };
         }
 
-        // if the tile is within 4 tiles of the edge of the map, return boundary
-        if x < 4 || y < 4 || x >= width - 8 || y >= height - 8 {
+        // if the tile is within the boundary margin of the edge of the map, return boundary
+        if config.is_boundary(x, y) {
             return Tile {
                 terrain_type: TerrainType::Boundary,
                 x_position: x,
                 y_position: y,
                 elevation: 0,
+                moisture: 0,
+                temperature: 0,
+                depth: 0,
+                zone: Zone::Wild,
+                spawn: None,
             };
         }
 
 
-        let mut calculate_biased_elevation = |x: f64, y: f64, neighbors: &[(f64, f64)], map_width: f64, map_height: f64| -> f64 {
-            let elevation = Self::calc_elevation(width, height, x, y, map_width, map_height);
+        let mut calculate_biased_elevation = |x: f64, y: f64, neighbors: &[(f64, f64)]| -> f64 {
+            let elevation = height(x, y);
             let mut sum_elevation = elevation;
             let mut count = 1.0;
             for (neighbor_x, neighbor_y) in neighbors {
-                let neighbor_elevation = Self::calc_elevation(width, height, *neighbor_x, *neighbor_y, map_width, map_height);
+                let neighbor_elevation = height(*neighbor_x, *neighbor_y);
                 sum_elevation += neighbor_elevation;
                 count += 1.0;
                 // print elevation and neighbor elevation
@@ -322,27 +454,26 @@ impl Tile {
                     is_beach = true;
                 }
             }
-            let average_elevation = sum_elevation / count;
-            average_elevation
+            sum_elevation / count
         };
 
 
-        let mut calculate_elevation = |x: f64, y: f64, width: i32, height: i32| -> i32 {
+        let mut calculate_elevation = |x: f64, y: f64| -> i32 {
             let neighbors = [(x - 1.0, y), (x + 1.0, y), (x, y - 1.0), (x, y + 1.0)]; // only left, right, up, down neighbors
-            let elevation = calculate_biased_elevation(x as f64, y as f64, &neighbors, width as f64, height as f64);
+            let elevation = calculate_biased_elevation(x, y, &neighbors);
             elevation as i32
         };
 
-        let elevation = calculate_elevation(x.into(), y.into(), width, height);
+        let elevation = calculate_elevation(x.into(), y.into());
+        let moisture = biome::moisture_at(config, noise, x, y);
+        let temperature = biome::temperature_at(config, noise, x, y, elevation);
         let terrain_type = if elevation < 1 {
             TerrainType::Water
         } else if elevation >= 1 && is_beach {
             //print!("Beach ");
             TerrainType::Beach
-        } else if elevation < 100 {
-            TerrainType::Grass
         } else {
-            TerrainType::Mountain
+            biome::classify(elevation, moisture, temperature, config)
         };
 
         Tile {
@@ -350,29 +481,40 @@ impl Tile {
             x_position: x,
             y_position: y,
             elevation,
+            moisture,
+            temperature,
+            depth: 0,
+            zone: Zone::Wild,
+            spawn: None,
         }
     }
 
     // private static method to calculate elevation
-    fn calc_elevation(width: i32, height: i32, x: f64, y: f64, map_width: f64, map_height: f64) -> f64 {
-        let width_f64 = width as f64;
-        let height_f64 = height as f64;
-        let center_x = width_f64 / 2.0;
-        let center_y = height_f64 / 2.0;
-        let margin = 600.0;
-        let dx = x - center_x;
-        let dy = y - center_y;
-        let distance_to_center = (dx * dx + dy * dy).sqrt();
-        let max_distance = (width_f64 * width_f64 + height_f64 * height_f64).sqrt() - margin;
-        let normalized_distance = distance_to_center / max_distance;
-        let scale_factor = 60.0 * (1.0 - normalized_distance);
-        let noise_value = (&*PERLIN).get([x / map_width, y / map_height]);
+    fn calc_elevation(config: &WorldGenConfig, perlin: &noise::Perlin, x: f64, y: f64) -> f64 {
+        let scale_factor = config.elevation_scale * shape::falloff(config, perlin, x, y);
+        let noise_value = shape::elevation_noise(config, perlin, x, y);
         let normalized_noise_value = (noise_value + 1.0) / 2.0; // Normalize to 0-1
         let scaled_noise_value = normalized_noise_value * scale_factor;
-        scaled_noise_value - 20.0
+        scaled_noise_value - config.sea_level
     }
 
 
+    // ground that is not sea, river, lake or the edge of the world
+    pub fn is_land(&self) -> bool {
+        !matches!(self.terrain_type,
+            TerrainType::Limbo | TerrainType::Boundary | TerrainType::Water | TerrainType::River | TerrainType::Lake)
+    }
+
+    // shallow rivers and lake shores can be waded through
+    pub fn is_fordable(&self) -> bool {
+        self.depth <= hydrology::FORDABLE_DEPTH
+    }
+
+    // the colour of the tile in the theme in use, see palette.rs
+    pub fn color(&self) -> Color {
+        palette::current().terrain(self.terrain_type, self.elevation)
+    }
+
     pub fn execute_command(&self, command: Command) -> Self {
         match command {
             Command::MoveTo(x, y) => {
@@ -392,7 +534,7 @@ impl Tile {
 }
 
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum TerrainType {
     Limbo,
     Boundary,
@@ -401,14 +543,90 @@ pub enum TerrainType {
     Beach,
     Water,
     Mountain,
+    Forest,
+    Desert,
+    Tundra,
+    Swamp,
+    Jungle,
+    Snow,
+    River,
+    Lake,
+    Wall,
+    Floor,
+    Door,
+    StairsUp,
+    StairsDown,
+    City,
+    Road,
+    Bridge,
     // Add other terrain types here...
 }
 
+// terrain types by name, as written in data files
+impl FromStr for TerrainType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Limbo" => Ok(TerrainType::Limbo),
+            "Boundary" => Ok(TerrainType::Boundary),
+            "Earth" => Ok(TerrainType::Earth),
+            "Grass" => Ok(TerrainType::Grass),
+            "Beach" => Ok(TerrainType::Beach),
+            "Water" => Ok(TerrainType::Water),
+            "Mountain" => Ok(TerrainType::Mountain),
+            "Forest" => Ok(TerrainType::Forest),
+            "Desert" => Ok(TerrainType::Desert),
+            "Tundra" => Ok(TerrainType::Tundra),
+            "Swamp" => Ok(TerrainType::Swamp),
+            "Jungle" => Ok(TerrainType::Jungle),
+            "Snow" => Ok(TerrainType::Snow),
+            "River" => Ok(TerrainType::River),
+            "Lake" => Ok(TerrainType::Lake),
+            "Wall" => Ok(TerrainType::Wall),
+            "Floor" => Ok(TerrainType::Floor),
+            "Door" => Ok(TerrainType::Door),
+            "StairsUp" => Ok(TerrainType::StairsUp),
+            "StairsDown" => Ok(TerrainType::StairsDown),
+            "City" => Ok(TerrainType::City),
+            "Road" => Ok(TerrainType::Road),
+            "Bridge" => Ok(TerrainType::Bridge),
+            _ => Err(format!("Unknown terrain: {}", s)),
+        }
+    }
+}
+
 // derive clone
-#[derive(Clone, Debug, PartialEq)]
+// the map is fully described by its config, the noise is rebuilt from the seed
+// config, noise and tile cache are shared, so cloning a map is cheap
+#[derive(Clone, Debug)]
 pub struct GameMap {
     width: i32,
     height: i32,
+    config: Arc<WorldGenConfig>,
+    noise: Arc<WorldNoise>,
+    tiles: Arc<TileCache>,
+    // the eroded height field, when erosion is turned on, otherwise heights come from the noise
+    elevation: Option<Arc<ElevationGrid>>,
+    // tiles changed by the generation passes that run after the noise, such as rivers
+    features: Arc<HashMap<(i32, i32), Tile>>,
+    // how far underground the map is, 0 is the overworld
+    depth: i32,
+    // the walls and floors of an underground level, the overworld has none
+    level: Option<Arc<Level>>,
+    // the villages, towns and cities, best placed first
+    settlements: Arc<Vec<Settlement>>,
+    // where monsters, items, characters and NPCs appear
+    spawns: Arc<HashMap<(i32, i32), SpawnKind>>,
+    // the named landmasses, mountain ranges, lakes and bays
+    regions: Arc<Regions>,
+}
+
+// two maps are the same map when they were generated from the same config at the same depth
+impl PartialEq for GameMap {
+    fn eq(&self, other: &Self) -> bool {
+        self.config == other.config && self.depth == other.depth
+    }
 }
 
 impl GameObject for GameMap {
@@ -419,9 +637,14 @@ impl GameObject for GameMap {
         (*self).clone()
     }
 
-    // return tile at x,y by creating new tile
+    // return tile at x,y from the tile cache, creating its chunk if needed
     fn get_tile(&self, x: i32, y: i32) -> Tile {
-        Tile::new(x, y, self.width, self.height)
+        let x = self.config.wrap_x(x);
+        // off the map is always limbo, no point caching it
+        if !self.config.in_bounds(x, y) {
+            return self.generate_tile(x, y);
+        }
+        self.tiles.get_or_generate(x, y, |x, y| self.generate_tile(x, y))
     }
 
 
@@ -429,10 +652,135 @@ impl GameObject for GameMap {
 }
 
 impl GameMap {
-    pub fn new(width: i32, height: i32) -> Self {
+    pub fn new(config: WorldGenConfig) -> Self {
+        Self::with_cache_capacity(config, tile_cache::DEFAULT_MAX_CHUNKS)
+    }
+
+    // a map that keeps at most max_chunks chunks of tiles in memory
+    pub fn with_cache_capacity(config: WorldGenConfig, max_chunks: usize) -> Self {
+        let mut game_map = GameMap {
+            width: config.width,
+            height: config.height,
+            noise: Arc::new(WorldNoise::new(config.seed)),
+            config: Arc::new(config),
+            tiles: Arc::new(TileCache::new(max_chunks)),
+            elevation: None,
+            features: Arc::new(HashMap::new()),
+            depth: 0,
+            level: None,
+            settlements: Arc::new(Vec::new()),
+            spawns: Arc::new(HashMap::new()),
+            regions: Arc::new(Regions::default()),
+        };
+        if let Some(erosion) = &game_map.config.erosion {
+            let mut grid = ElevationGrid::from_fn(game_map.width, game_map.height, |x, y| game_map.noise_elevation_at(x, y));
+            erosion::erode(&mut grid, erosion, game_map.config.seed);
+            game_map.elevation = Some(Arc::new(grid));
+        }
+        // the passes read the terrain as it stands so far, nothing has been cached yet
+        game_map.features = Arc::new(hydrology::generate(&game_map));
+        let (settlements, towns_and_roads) = settlement::generate(&game_map);
+        let mut features = (*game_map.features).clone();
+        features.extend(towns_and_roads);
+        game_map.features = Arc::new(features);
+        game_map.settlements = Arc::new(settlements);
+        let mut features = (*game_map.features).clone();
+        features.extend(dungeon::place_entrances(&game_map));
+        game_map.features = Arc::new(features);
+        game_map.spawns = Arc::new(zones::place_spawns(&game_map));
+        game_map.regions = Arc::new(regions::generate(&game_map));
+        game_map
+    }
+
+    // an underground level, its tiles come from the level layout instead of the noise
+    pub fn underground(config: WorldGenConfig, depth: i32, level: Level) -> Self {
         GameMap {
-            width,
-            height,
+            width: config.width,
+            height: config.height,
+            noise: Arc::new(WorldNoise::new(config.seed)),
+            config: Arc::new(config),
+            tiles: Arc::new(TileCache::new(tile_cache::DEFAULT_MAX_CHUNKS)),
+            elevation: None,
+            features: Arc::new(HashMap::new()),
+            depth,
+            level: Some(Arc::new(level)),
+            settlements: Arc::new(Vec::new()),
+            spawns: Arc::new(HashMap::new()),
+            regions: Arc::new(Regions::default()),
+        }
+    }
+
+    pub fn depth(&self) -> i32 {
+        self.depth
+    }
+
+    pub fn settlements(&self) -> &[Settlement] {
+        &self.settlements
+    }
+
+    pub fn regions(&self) -> &Regions {
+        &self.regions
+    }
+
+    // the settlement x,y is in, if any
+    pub fn settlement_at(&self, x: i32, y: i32) -> Option<&Settlement> {
+        self.settlements.iter().find(|settlement| settlement.contains(x, y))
+    }
+
+    pub fn config(&self) -> &WorldGenConfig {
+        &self.config
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        self.tiles.stats()
+    }
+
+    // build the cached tiles for a rectangle of the map in parallel
+    pub fn prefetch(&self, x0: i32, y0: i32, x1: i32, y1: i32) {
+        let x1 = x1.min(self.width - 1);
+        let y1 = y1.min(self.height - 1);
+        self.tiles.prefetch(max(0, x0), max(0, y0), x1, y1, |x, y| self.generate_tile(x, y));
+    }
+
+    // the height of the land at x,y, before it is rounded into tiles
+    pub fn elevation_at(&self, x: i32, y: i32) -> f64 {
+        match &self.elevation {
+            Some(grid) if self.config.in_bounds(x, y) => grid.get(x, y),
+            _ => self.noise_elevation_at(x, y),
+        }
+    }
+
+    // the height straight from the noise, before any erosion
+    fn noise_elevation_at(&self, x: i32, y: i32) -> f64 {
+        Tile::calc_elevation(&self.config, &self.noise.elevation, x as f64, y as f64)
+    }
+
+    // derive the tile at x,y from the heights and the generated features, bypassing the cache
+    fn generate_tile(&self, x: i32, y: i32) -> Tile {
+        zones::annotate(self, self.generate_terrain(x, y))
+    }
+
+    // the tile before its zone and spawn point are filled in
+    fn generate_terrain(&self, x: i32, y: i32) -> Tile {
+        if let Some(tile) = self.features.get(&(x, y)) {
+            return tile.clone();
+        }
+        if let Some(level) = &self.level {
+            return Tile {
+                terrain_type: level.get(x, y),
+                x_position: x,
+                y_position: y,
+                elevation: 0,
+                moisture: 0,
+                temperature: 0,
+                depth: 0,
+                zone: Zone::Wild,
+                spawn: None,
+            };
+        }
+        match &self.elevation {
+            Some(_) => Tile::from_elevation(x, y, &self.config, &self.noise, &|x, y| self.elevation_at(x as i32, y as i32)),
+            None => Tile::new(x, y, &self.config, &self.noise),
         }
     }
 
@@ -452,27 +800,24 @@ impl GameMap {
     }
 
 
-    fn generate_map_image(&self, filename: &str) {
+    // the map as zoomable images in dir, see tile_pyramid.rs
+    pub fn export_tile_pyramid(&self, dir: &str) -> image::ImageResult<usize> {
+        tile_pyramid::export(self, dir)
+    }
+
+    // the terrain of the whole map, one pixel per tile
+    fn terrain_image(&self) -> RgbImage {
         let width = self.width as u32;
         let height = self.height as u32;
         let mut img = ImageBuffer::<Rgb<u8>, _>::new(width, height);
 
+        self.prefetch(0, 0, self.width - 1, self.height - 1);
+        let theme = palette::current();
         img.enumerate_pixels_mut().par_bridge().for_each(|(x, y, pixel)| {
             let tile = self.get_tile(x as i32, y as i32);
-            *pixel = match tile.terrain_type {
-                TerrainType::Water => Color::BLUE.to_rgb(),
-                TerrainType::Limbo => Color::FUCHSIA.to_rgb(),
-                TerrainType::Boundary => Color::WHITE.to_rgb(),
-                TerrainType::Earth | TerrainType::Grass | TerrainType::Mountain => {
-                    // generate a shade of green based on the elevation
-                    let elevation = tile.elevation as f32 / 100.0;
-                    let color = 100 - (elevation * 255.0) as u8;
-                    Rgb([0, color, 0])
-                }
-                TerrainType::Beach => Color::YELLOW.to_rgb(),
-            };
+            *pixel = theme.terrain(tile.terrain_type, tile.elevation).to_rgb();
         });
-        img.save(filename).unwrap();
+        img
     }
 }
 
@@ -490,17 +835,65 @@ pub struct Character {
     energy: i32,
     hydration: i32,
     health: i32,
+    mana: i32,
+    // strength, dexterity and intelligence and what modifies them, see attributes.rs
+    attributes: Attributes,
+    // experience, level and skills, see progress.rs
+    progress: Progress,
+    // poisoned, wet and the like, in the order they are applied, see status.rs
+    effects: Arc<Vec<StatusEffect>>,
     attack: i32,
     defense: i32,
     x_position: i32,
     y_position: i32,
+    // how far underground the character is, 0 is the overworld
+    depth: i32,
     facing: Direction,
+    // standing ready for an attack until the next turn, see combat.rs
+    defending: bool,
     // character has a bag of items
     bag: Vec<Item>,
+    // the map the character is walking on
+    game_map: GameMap,
+    // every tile the character has seen, and the tiles in sight now
+    explored: Arc<Explored>,
+    visible: Arc<HashSet<(i32, i32)>>,
+    // every tile the character has set foot on
+    visited: Arc<Explored>,
 
 }
 
 impl Character {
+    // the character after taking in what it can see from where it stands.
+    // takes the character so its memory of the map is only copied when someone else still holds it
+    pub fn look_around(self) -> Self {
+        let visible = fov::field_of_view(&self.game_map, self.x_position, self.y_position);
+        let mut explored = self.explored;
+        Arc::make_mut(&mut explored).mark(&self.game_map, &visible);
+        Character {
+            explored,
+            visible: Arc::new(visible),
+            ..self
+        }
+    }
+
+    // no one fights in a safe zone, see zones.rs
+    pub fn in_safe_zone(&self) -> bool {
+        self.get_tile(self.x_position, self.y_position).zone == Zone::Safe
+    }
+
+    // how much the character knows about x,y on the level it is on
+    pub fn seen(&self, x: i32, y: i32) -> Seen {
+        let x = self.game_map.config().wrap_x(x);
+        if self.visible.contains(&(x, y)) {
+            Seen::Visible
+        } else if self.explored.contains(self.depth, x, y) {
+            Seen::Remembered
+        } else {
+            Seen::Unexplored
+        }
+    }
+
     pub fn execute_command(&self, command: Command) -> Self {
         match command {
             Command::MoveTo(x, y) => {
@@ -512,26 +905,72 @@ impl Character {
                     ..(*self).clone()
                 }
             }
-            Command::Attack => {
-                println!("{} attacks", self.name);
-                // Here you can add the logic for the character to attack
-                // For example, you might decrease the health of the attacked character
-                // and return a new Character with the updated health
-                // For now, we'll just return the character as is
-                (*self).clone()
-            }
+            // the world knows who is standing where, see World::attack
+            Command::Attack(_) => { (*self).clone() }
             Command::Defend => {
                 println!("{} defends", self.name);
-                // Here you can add the logic for the character to defend
-                // For example, you might increase the defense of the character
-                // and return a new Character with the updated defense
-                // For now, we'll just return the character as is
-                (*self).clone()
+                Character {
+                    defending: true,
+                    ..(*self).clone()
+                }
             }
             Command::Idle => { (*self).clone() }
             Command::Quit => { (*self).clone() }
             Command::Test => { (*self).clone() }
-
+            Command::Stats => { (*self).clone() }
+            // the world knows where stairs lead, see World::take_stairs
+            Command::Descend => { (*self).clone() }
+            Command::Climb => { (*self).clone() }
+            Command::Towns => { (*self).clone() }
+            Command::Map(_, _, _) => { (*self).clone() }
+            Command::Export(_, _, _, _) => { (*self).clone() }
+            Command::Tiles(_) => { (*self).clone() }
+            Command::Record(_) => { (*self).clone() }
+            Command::Heatmap(_, _, _, _) => { (*self).clone() }
+            Command::Svg(_, _, _) => { (*self).clone() }
+            Command::Screenshot(_, _, _, _) => { (*self).clone() }
+            Command::Theme(_, _) => { (*self).clone() }
+
+            Command::Use(name) => {
+                let Some(index) = self.bag.iter().position(|item| item.name == name) else {
+                    println!("{} has no {}", self.name, name);
+                    return (*self).clone();
+                };
+                let item = &self.bag[index];
+                let used = match item.item_type {
+                    ItemType::Food => {
+                        println!("{} eats the {}", self.name, item.name);
+                        let energy = self.energy + item.value * ENERGY_PER_FOOD_VALUE + progress::foraging_bonus(self, item.value);
+                        progress::practise(&Character { energy, ..(*self).clone() }, Skill::Foraging)
+                    }
+                    ItemType::Potion => {
+                        let modifier = attributes::potion(&item.name, item.value);
+                        println!("{} drinks the {}, {} {:+} for {} turns", self.name, item.name,
+                                 modifier.attribute.name(), modifier.amount, modifier.turns.unwrap_or(0));
+                        let drunk = Character { attributes: self.attributes.with_modifier(modifier), ..(*self).clone() };
+                        status::afflict(&drunk, EffectKind::Regenerating, status::REGENERATING_TURNS)
+                    }
+                    _ => {
+                        println!("{} cannot use the {}", self.name, item.name);
+                        return (*self).clone();
+                    }
+                };
+                let mut bag = used.bag.clone();
+                bag.remove(index);
+                Character { bag, ..used }
+            }
+            Command::Train(attribute) => {
+                match progress::train(self, attribute) {
+                    Some(trained) => {
+                        println!("{} trains {} to {}", self.name, attribute.name(), trained.attributes.base(attribute));
+                        trained
+                    }
+                    None => {
+                        println!("{} has no attribute points to spend", self.name);
+                        (*self).clone()
+                    }
+                }
+            }
             Command::AddItem(item) => {
                 // create a new player with the item added to the bag
                 let mut new_bag = self.bag.clone();
@@ -555,58 +994,92 @@ impl Character {
                 (*self).clone()
             }
             Command::Move => {
-                match self.do_move() {
+                match (*self).clone().do_move() {
                     Ok(value) => value,
-                    Err(value) => return value,
+                    Err(value) => value,
                 }
             }
         }
     }
 
-    fn do_move(&self) -> Result<Character, Character> {
+    // a failed move hands back the character unchanged
+    #[allow(clippy::result_large_err)]
+    fn do_move(self) -> Result<Character, Character> {
     // advance in the direction we are facing
         let (x, y) = (self.x_position + self.facing.get_offset().0, self.y_position + self.facing.get_offset().1);
+        // walking off one side of a wrap-around world comes back on the other
+        let x = self.game_map.config().wrap_x(x);
         println!("{} moves to ({}, {})", self.name, x, y);
         // get the tile for x,y
-        let tile = Tile::new(x, y, 2048, 2048);
+        let tile = self.get_tile(x, y);
 
         // if the tile is a boundary do not move
         if tile.terrain_type == TerrainType::Boundary {
             println!("{} cannot move to ({}, {}) because it is a boundary", self.name, x, y);
-            return Err((*self).clone());
+            return Err(self);
+        }
+        // nor through solid rock underground
+        if tile.terrain_type == TerrainType::Wall {
+            println!("{} cannot move to ({}, {}) because it is a wall", self.name, x, y);
+            return Err(self);
         }
         // if the tile is water do not move
         if tile.terrain_type == TerrainType::Water {
             println!("{} cannot move to ({}, {}) because it is water", self.name, x, y);
-            return Err((*self).clone());
+            return Err(self);
+        }
+        // rivers and lakes can only be crossed where they are shallow
+        let is_fresh_water = tile.terrain_type == TerrainType::River || tile.terrain_type == TerrainType::Lake;
+        if is_fresh_water && !tile.is_fordable() {
+            println!("{} cannot move to ({}, {}) because the {:?} is too deep", self.name, x, y, tile.terrain_type);
+            return Err(self);
         }
 
         // what is the elevation difference between the current tile and the new tile
         let elevation_diff = tile.elevation - self.get_tile(self.x_position, self.y_position).elevation;
         let mut energy = self.energy;
-        energy = energy-1;
+        energy -= attributes::move_cost(&self).total;
         // if the elevation difference is greater than 1, reduce energy further
-        if elevation_diff.abs() > 1 {
-            energy = energy - (elevation_diff.abs() / 2);
+        let climbing = elevation_diff.abs() > 1;
+        if climbing {
+            energy -= progress::climbing_cost(&self, elevation_diff.abs() / 2);
             println!("{} moves to ({}, {}) with an elevation difference of {} and energy {}", self.name, x, y, elevation_diff, energy);
         }
+        // wading is hard work
+        if is_fresh_water {
+            energy -= progress::wading_cost(&self, 2);
+            println!("{} fords the {:?} at ({}, {})", self.name, tile.terrain_type, x, y);
+        }
 
 
-        Ok(Character {
+        let mut moved = Character {
             x_position: x,
             y_position: y,
             energy,
             hydration: self.hydration-1,
-            ..(*self).clone()
-        })
+            ..self
+        }.look_around();
+        if climbing {
+            moved = progress::practise(&moved, Skill::Climbing);
+        }
+        if is_fresh_water {
+            moved = progress::practise(&moved, Skill::Swimming);
+        }
+        // setting foot somewhere new is worth a little experience
+        if !moved.visited.contains(moved.depth, x, y) {
+            Arc::make_mut(&mut moved.visited).mark(&moved.game_map, &HashSet::from([(x, y)]));
+            moved = progress::gain(&moved, progress::EXPLORE_EXPERIENCE);
+        }
+        Ok(moved)
     }
 }
 
 impl fmt::Display for Character {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Character {{ character_type: {:?}, name: {}, health: {}, energy: {}, hydration: {}, x: {}, y: {}, elevation: {}, bag: {:?} }}",
-               self.character_type, self.name, self.health, self.energy, self.hydration,
-               self.x_position, self.y_position, self.get_tile(self.x_position, self.y_position).elevation, self.bag)
+        write!(f, "Character {{ character_type: {:?}, name: {}, health: {}, mana: {}, energy: {}, hydration: {}, x: {}, y: {}, depth: {}, elevation: {}, bag: {:?} }} {}",
+               self.character_type, self.name, self.health, self.mana, self.energy, self.hydration,
+               self.x_position, self.y_position, self.depth, self.get_tile(self.x_position, self.y_position).elevation, self.bag,
+               regions::describe(&self.game_map, self.x_position, self.y_position))
     }
 }
 
@@ -617,23 +1090,25 @@ impl GameObject for Character {
         // reduce energy and hydration
         let energy = max(0, self.energy - 1);
         let hydration = max(0, self.hydration - 1);
-        // reduce health if energy or hydration is zero
-        let health = if energy == 0 || hydration == 0 {
-            max(0, self.health - 1)
-        } else {
-            self.health
-        };
+        // going hungry or thirsty and standing in water put effects on, then every effect has its turn
+        let affected = status::from_surroundings(&Character { energy, hydration, ..(*self).clone() });
+        let affected = status::tick(&affected);
+        // no more health than strength allows, and mana slowly comes back
+        let health = affected.health.min(attributes::max_health(&affected).total);
+        let mana = (self.mana + 1).min(attributes::max_mana(&affected).total);
         // create a new character with the updated attributes
         Character {
-            energy,
-            hydration,
             health,
-            ..(*self).clone()
+            mana,
+            attributes: self.attributes.tick(),
+            // the turn is over, and with it any stance
+            defending: false,
+            ..affected
         }
     }
 
     fn get_tile(&self, x: i32, y: i32) -> Tile {
-        Tile::new(x, y, 2048, 2048)
+        self.game_map.get_tile(x, y)
     }
     // other common methods...
 }
@@ -689,6 +1164,8 @@ impl Item {
 #[derive(Clone, Debug, PartialEq)]
 pub struct Items {
     items: Vec<Item>,
+    // the map the items are lying on
+    game_map: GameMap,
 }
 
 impl GameObject for Items {
@@ -701,7 +1178,7 @@ impl GameObject for Items {
     }
 
     fn get_tile(&self, x: i32, y: i32) -> Tile {
-        Tile::new(x, y, 2048, 2048)
+        self.game_map.get_tile(x, y)
     }
     // other common methods...
 }
@@ -712,6 +1189,8 @@ pub enum GameEntity {
     Character(Character),
     GameMap(GameMap),
     Items(Items),
+    // what is left of a dead character, see death.rs
+    Corpse(Corpse),
     // other game entities...
 }
 
@@ -721,6 +1200,7 @@ impl GameObject for GameEntity {
             GameEntity::Character(character) => GameEntity::Character(character.update(world)),
             GameEntity::GameMap(game_map) => GameEntity::GameMap(game_map.update(world)),
             GameEntity::Items(items) => GameEntity::Items(items.update(world)),
+            GameEntity::Corpse(corpse) => GameEntity::Corpse(corpse.update(world)),
             // other game entities...
         }
     }
@@ -730,20 +1210,39 @@ impl GameObject for GameEntity {
             GameEntity::Character(character) => character.get_tile(x, y),
             GameEntity::GameMap(game_map) => game_map.get_tile(x, y),
             GameEntity::Items(items) => items.get_tile(x, y),
+            GameEntity::Corpse(corpse) => corpse.get_tile(x, y),
             // other game entities...
         }
     }
     // other common methods...
 }
 
+// the energy a bite of food gives back for each point it is worth
+const ENERGY_PER_FOOD_VALUE: i32 = 10;
+
+// how likely a wandering character is to set off in a new direction each tick
+const WANDER_TURN_CHANCE: f64 = 0.2;
+
 // Define the world state
 pub struct World {
     height: i32,
     width: i32,
     entities: Vec<GameEntity>,
     game_map: GameMap,
+    // the levels under the overworld, depth 1 first
+    underground: Vec<GameMap>,
+    // where each set of stairs leads
+    stairs: StairLinks,
     items_by_name: HashMap<String, Item>,
     items_by_position: HashMap<(i32, i32), Item>,
+    // what happens on every tick, while recording
+    recorder: Option<Recorder>,
+    // where steps, fights and deaths happened on the overworld
+    tallies: Tallies,
+    // deaths not reported yet, the dead waiting to come back, and where the player comes back
+    deaths: Vec<DeathEvent>,
+    respawns: Vec<Respawn>,
+    respawn_point: (i32, i32),
 
 
     // other world state...
@@ -754,17 +1253,89 @@ impl World {
     pub fn update(&self) -> Self {
         println!("World updated");
         World {
-            height: 2048,
-            width: 2048,
+            height: self.height,
+            width: self.width,
             entities: self.entities.iter().map(|entity| entity.update(self)).collect(),
-            // create new game map with updated tiles
-            game_map: GameMap::new(self.width, self.height),
+            // the map is generated from its config, so it carries over unchanged
+            game_map: self.game_map.clone(),
+            underground: self.underground.clone(),
+            stairs: self.stairs.clone(),
             // other world state...
             items_by_name: self.items_by_name.clone(),
             items_by_position: self.items_by_position.clone(),
+            // carried over by advance, so the recording is not copied every tick
+            recorder: None,
+            tallies: self.tallies.clone(),
+            deaths: self.deaths.clone(),
+            respawns: self.respawns.clone(),
+            respawn_point: self.respawn_point,
+        }
+    }
+
+    // move on to the next tick, recording it if the recorder is running
+    fn advance(&mut self) {
+        self.monsters_attack();
+        let recorder = self.recorder.take();
+        *self = self.update();
+        // poison, hunger and thirst can kill as well as blows
+        let dead: Vec<Character> = self.characters().filter(|character| character.health == 0).cloned().collect();
+        for character in dead {
+            self.remove_named_character(character.name.clone());
+            let cause = death::cause(&character);
+            self.die(character, cause);
+        }
+        self.bring_back();
+        if let Some(mut recorder) = recorder {
+            recorder.capture(self);
+            self.recorder = Some(recorder);
+        }
+    }
+
+    // run the world on its own for a number of ticks, every character wandering about
+    pub fn simulate(&mut self, ticks: usize) {
+        for _ in 0..ticks {
+            self.wander();
+            self.advance();
+            self.report_deaths();
         }
     }
 
+    // every character takes a step, turning now and then, and turning away when it can not go on
+    fn wander(&mut self) {
+        let mut rng = rand::thread_rng();
+        // taken out of the world so each character is moved on its own, not a copy of it
+        self.entities = std::mem::take(&mut self.entities).into_iter()
+            .map(|entity| match entity {
+                GameEntity::Character(character) => {
+                    let facing = if rng.gen_bool(WANDER_TURN_CHANCE) { Direction::random_direction() } else { character.facing };
+                    let character = Character { facing, ..character };
+                    GameEntity::Character(match character.do_move() {
+                        Ok(moved) => {
+                            Self::count_step(&mut self.tallies, &moved);
+                            moved
+                        }
+                        Err(stuck) => Character { facing: stuck.facing.turn_right(), ..stuck },
+                    })
+                }
+                other => other,
+            })
+            .collect();
+    }
+
+    // a footstep where a character has just moved to, on the overworld
+    fn count_step(tallies: &mut Tallies, moved: &Character) {
+        if moved.depth == 0 {
+            heatmap::add(&mut tallies.footsteps, moved.x_position, moved.y_position);
+        }
+    }
+
+    fn characters(&self) -> impl Iterator<Item = &Character> {
+        self.entities.iter().filter_map(|entity| match entity {
+            GameEntity::Character(character) => Some(character),
+            _ => None,
+        })
+    }
+
     pub fn command_loop(&mut self) {
         let (tx, rx) = mpsc::channel();
         thread::spawn(move || {
@@ -792,10 +1363,14 @@ impl World {
                         }
                         let command = self.parse_command(command);
                         self.execute_command(command);
+                        self.report_deaths();
                     }
                     println!("Enter command: ");
                 }
-                Err(_) => { self.execute_command(Command::Idle) }
+                Err(_) => {
+                    self.execute_command(Command::Idle);
+                    self.report_deaths();
+                }
             }
         }
     }
@@ -804,11 +1379,105 @@ impl World {
         let command = command.trim();
         let parts: Vec<&str> = command.split_whitespace().collect();
 
-        match parts.get(0) {
+        match parts.first() {
             Some(&"quit") => Command::Quit,
             Some(&"test") => Command::Test,
             Some(&"me") => Command::Me,
             Some(&"move") => Command::Move,
+            Some(&"attack") if parts.len() == 2 => Command::Attack(parts[1].to_string()),
+            Some(&"defend") => Command::Defend,
+            Some(&"train") if parts.len() == 2 => match parts[1].parse() {
+                Ok(attribute) => Command::Train(attribute),
+                Err(e) => {
+                    println!("{}", e);
+                    Command::Idle
+                }
+            },
+            Some(&"use") if parts.len() >= 2 => Command::Use(parts[1..].join(" ")),
+            Some(&"stats") => Command::Stats,
+            Some(&"down") => Command::Descend,
+            Some(&"up") => Command::Climb,
+            Some(&"towns") => Command::Towns,
+            Some(&"tiles") if parts.len() == 2 => Command::Tiles(parts[1].to_string()),
+            // record, then record <file.gif|dir> to stop and save
+            Some(&"record") if parts.len() == 1 => Command::Record(None),
+            Some(&"record") if parts.len() == 2 => Command::Record(Some(parts[1].to_string())),
+            // svg <file.svg> [contours=height] [step=tiles]
+            Some(&"svg") if parts.len() >= 2 => {
+                let mut interval = svg_export::DEFAULT_CONTOUR_INTERVAL;
+                let mut step = svg_export::DEFAULT_STEP;
+                for option in &parts[2..] {
+                    match option.split_once('=').map(|(name, value)| (name, value.parse::<f64>())) {
+                        Some(("contours", Ok(value))) => interval = value,
+                        Some(("step", Ok(value))) => step = value as i32,
+                        _ => return Command::Idle,
+                    }
+                }
+                Command::Svg(parts[1].to_string(), interval, step)
+            }
+            // theme, or theme <name> [palette file]
+            Some(&"theme") if parts.len() <= 3 => Command::Theme(parts.get(1).map(|name| name.to_string()), parts.get(2).map(|file| file.to_string())),
+            // screenshot <file.png> [mapping file] [width height]
+            Some(&"screenshot") if parts.len() >= 2 => {
+                let (mapping, size) = match parts[2..].first() {
+                    Some(mapping) if mapping.parse::<i32>().is_err() => (mapping.to_string(), &parts[3..]),
+                    _ => (sprites::DEFAULT_MAPPING.to_string(), &parts[2..]),
+                };
+                match size {
+                    [] => Command::Screenshot(parts[1].to_string(), mapping, sprites::DEFAULT_WIDTH, sprites::DEFAULT_HEIGHT),
+                    [width, height] => match (width.parse(), height.parse()) {
+                        (Ok(width), Ok(height)) => Command::Screenshot(parts[1].to_string(), mapping, width, height),
+                        _ => Command::Idle,
+                    },
+                    _ => Command::Idle,
+                }
+            }
+            // heatmap <items|footsteps|combat|deaths> <file.png> [heat|cool|mono] [cell size]
+            Some(&"heatmap") if parts.len() >= 3 => {
+                let Ok(layer) = parts[1].parse() else { return Command::Idle };
+                let mut ramp = Ramp::Heat;
+                let mut cell = heatmap::DEFAULT_CELL;
+                for option in &parts[3..] {
+                    if let Ok(chosen) = option.parse() {
+                        ramp = chosen;
+                    } else if let Ok(size) = option.parse() {
+                        cell = size;
+                    } else {
+                        return Command::Idle;
+                    }
+                }
+                Command::Heatmap(layer, parts[2].to_string(), ramp, cell)
+            }
+            // map [width height] [fog]
+            Some(&"map") => {
+                let fog = parts.last() == Some(&"fog");
+                let size = if fog { &parts[1..parts.len() - 1] } else { &parts[1..] };
+                match size {
+                    [] => Command::Map(viewport::DEFAULT_WIDTH, viewport::DEFAULT_HEIGHT, fog),
+                    [width, height] => match (width.parse(), height.parse()) {
+                        (Ok(width), Ok(height)) => Command::Map(width, height, fog),
+                        _ => Command::Idle,
+                    },
+                    _ => Command::Idle,
+                }
+            }
+            // export <file> [terrain,items,characters,labels] [hillshade[=degrees]] [contours[=height]] [hypsometric] [view=name]
+            Some(&"export") if parts.len() >= 2 => {
+                let mut layers = MapLayers::default();
+                let mut style = MapStyle::default();
+                let mut view = None;
+                for option in &parts[2..] {
+                    if let Some(name) = option.strip_prefix("view=") {
+                        view = Some(name.to_string());
+                    } else if !style.set_option(option) {
+                        match MapLayers::from_names(option) {
+                            Some(chosen) => layers = chosen,
+                            None => return Command::Idle,
+                        }
+                    }
+                }
+                Command::Export(parts[1].to_string(), layers, style, view)
+            }
             Some(&"see") if parts.len() == 2 => Command::See(parts[1].parse().unwrap()),
             Some(&"look") if parts.len() == 2 => Command::Look(parts[1].parse().unwrap()),
             _ => Command::Idle,
@@ -822,13 +1491,32 @@ impl World {
                 // Here you can add the logic to move a character
                 println!("Move to ({}, {})", x, y);
             }
-            Command::Attack => {
-                // Here you can add the logic for a character to attack
-                println!("Attack");
+            Command::Attack(name) => {
+                match self.search_for_player() {
+                    Some(player) => self.attack(&player.name, &name),
+                    None => println!("Player not found"),
+                }
+            }
+            Command::Train(attribute) => {
+                if let Some(player) = self.search_for_player() {
+                    let new_player = player.execute_command(Command::Train(attribute));
+                    self.remove_named_character(player.name.clone());
+                    self.add_character(new_player);
+                }
+            }
+            Command::Use(name) => {
+                if let Some(player) = self.search_for_player() {
+                    let new_player = player.execute_command(Command::Use(name));
+                    self.remove_named_character(player.name.clone());
+                    self.add_character(new_player);
+                }
             }
             Command::Defend => {
-                // Here you can add the logic for a character to defend
-                println!("Defend");
+                if let Some(player) = self.search_for_player() {
+                    let new_player = player.execute_command(Command::Defend);
+                    self.remove_named_character(player.name.clone());
+                    self.add_character(new_player);
+                }
             }
             Command::Quit => {
                 // Here you can add the logic to quit the game
@@ -858,6 +1546,9 @@ impl World {
                 // Display the player
                 if let Some(player) = player_opt.as_ref() {
                     println!("{}", player);
+                    println!("{}", attributes::describe(player));
+                    println!("{}", progress::describe(player));
+                    println!("{}", status::describe(player));
                 }
 
                 // Get the player's position and items at that position
@@ -868,6 +1559,9 @@ impl World {
                     if let Some(item) = self.items_by_position.get(&(x, y)) {
                         println!("Item found: {:?}", item);
                     }
+                    for corpse in self.corpses_at(x, y, player.depth) {
+                        println!("The corpse of {} lies here with {} items", corpse.name, corpse.bag.len());
+                    }
                 }
 
             }
@@ -882,11 +1576,96 @@ impl World {
                     // get the character's position
                     let x = character.x_position;
                     let y = character.y_position;
-                    println!("{:?}", character.get_tile(x, y))
+                    println!("{:?}", character.get_tile(x, y));
+                    println!("{} is {}", character.name, regions::describe(&character.game_map, x, y));
                 } else {
                     println!("Character not found");
                 }
             }
+            Command::Stats => {
+                println!("{}", self.game_map.cache_stats());
+            }
+            Command::Descend => {
+                self.take_stairs(TerrainType::StairsDown);
+            }
+            Command::Climb => {
+                self.take_stairs(TerrainType::StairsUp);
+            }
+            Command::Towns => {
+                for settlement in self.game_map.settlements() {
+                    println!("{}", settlement);
+                }
+            }
+            Command::Map(width, height, fog) => {
+                match self.search_for_player() {
+                    Some(player) => print!("{}", viewport::render(self, &player, width, height, fog)),
+                    None => println!("Player not found"),
+                }
+            }
+            Command::Export(filename, layers, style, view) => {
+                let character = view.map(|name| self.search_for_named_character(name));
+                match character {
+                    Some(None) => println!("Character not found"),
+                    Some(Some(ref character)) if character.depth != 0 => println!("{} is not on the overworld", character.name),
+                    _ => match map_export::export(self, &filename, &layers, &style, character.flatten().as_ref()) {
+                        Ok(()) => println!("Map saved to {}", filename),
+                        Err(e) => println!("Failed to save {}: {}", filename, e),
+                    },
+                }
+            }
+            Command::Record(None) => {
+                println!("Recording");
+                self.recorder = Some(Recorder::default());
+            }
+            Command::Record(Some(path)) => {
+                match self.recorder.take() {
+                    Some(recorder) => match recorder.save(&self.game_map, &path) {
+                        Ok(()) => println!("Saved {} ticks to {}", recorder.tick_count(), path),
+                        Err(e) => println!("Failed to save the recording to {}: {}", path, e),
+                    },
+                    None => println!("Not recording"),
+                }
+            }
+            Command::Heatmap(layer, filename, ramp, cell) => {
+                let counts = heatmap::counts(self, layer);
+                match heatmap::export(self, &counts, &filename, ramp, cell) {
+                    Ok(()) => println!("Heatmap of {} tiles saved to {}", counts.len(), filename),
+                    Err(e) => println!("Failed to save {}: {}", filename, e),
+                }
+            }
+            Command::Svg(filename, interval, step) => {
+                match svg_export::export(self, &filename, interval, step) {
+                    Ok(()) => println!("Map saved to {}", filename),
+                    Err(e) => println!("Failed to save {}: {}", filename, e),
+                }
+            }
+            Command::Theme(None, _) => {
+                println!("Drawing in {}, the themes are {}", palette::current().name, palette::Palette::built_in().theme_names().join(", "));
+            }
+            Command::Theme(Some(name), filename) => {
+                match palette::use_theme(&name, filename.as_deref()) {
+                    Ok(()) => println!("Drawing in {}", name),
+                    Err(e) => println!("{}", e),
+                }
+            }
+            Command::Screenshot(filename, mapping, width, height) => {
+                let width = width.clamp(1, viewport::MAX_WIDTH);
+                let height = height.clamp(1, viewport::MAX_HEIGHT);
+                match (self.search_for_player(), sprites::Atlas::load(&mapping)) {
+                    (None, _) => println!("Player not found"),
+                    (_, Err(e)) => println!("{}", e),
+                    (Some(player), Ok(atlas)) => match sprites::screenshot(self, &player, &atlas, width, height).save(&filename) {
+                        Ok(()) => println!("Screenshot of {}x{} tiles drawn with {} sprites saved to {}", width, height, atlas.sprite_count(), filename),
+                        Err(e) => println!("Failed to save {}: {}", filename, e),
+                    },
+                }
+            }
+            Command::Tiles(dir) => {
+                match self.game_map.export_tile_pyramid(&dir) {
+                    Ok(count) => println!("Saved {} map tiles to {}, open {}/index.html to browse them", count, dir, dir),
+                    Err(e) => println!("Failed to save map tiles to {}: {}", dir, e),
+                }
+            }
             // add command "me look"
             Command::MeLook => {
                 // look around the player
@@ -896,17 +1675,20 @@ impl World {
                 let player = self.search_for_player();
                 if let Some(player) = player {
                     let new_player = player.execute_command(Command::Move);
+                    if (new_player.x_position, new_player.y_position) != (player.x_position, player.y_position) {
+                        Self::count_step(&mut self.tallies, &new_player);
+                    }
                     self.remove_named_character(player.name.clone());
                     self.add_character(new_player);
                 }
             }
         }
         // After executing the command, update the world state
-        *self = self.update();
+        self.advance();
     }
 
     fn search_for_player(&mut self) -> Option<Character> {
-        let player = self.entities.iter().find_map(|entity| {
+        self.entities.iter().find_map(|entity| {
             match entity {
                 GameEntity::Character(character) => {
                     if character.character_type == CharacterType::Player {
@@ -917,8 +1699,188 @@ impl World {
                 }
                 _ => None,
             }
+        })
+    }
+
+    // the map at a depth, 0 is the overworld
+    pub fn level(&self, depth: i32) -> Option<&GameMap> {
+        if depth == 0 {
+            Some(&self.game_map)
+        } else {
+            self.underground.get((depth - 1) as usize)
+        }
+    }
+
+    // attacker strikes at target, who must be standing next to it, and the outcome is reported,
+    // a target brought down dies and leaves its bag on the ground
+    fn attack(&mut self, attacker_name: &str, target_name: &str) {
+        let Some(attacker) = self.search_for_named_character(attacker_name.to_string()) else { return };
+        let Some(target) = self.search_for_named_character(target_name.to_string()) else {
+            println!("Character not found");
+            return;
+        };
+        if attacker.name == target.name {
+            println!("{} cannot attack itself", attacker.name);
+            return;
+        }
+        let Some(facing) = combat::adjacent(&attacker, &target) else {
+            println!("{} is not next to {}", target.name, attacker.name);
+            return;
+        };
+        if attacker.in_safe_zone() || target.in_safe_zone() {
+            println!("{} cannot fight in a safe zone", attacker.name);
+            return;
+        }
+        if attacker.depth == 0 {
+            heatmap::add(&mut self.tallies.combat, attacker.x_position, attacker.y_position);
+        }
+
+        let blow = combat::strike(&mut rand::thread_rng(), &attacker, &target);
+        let with = blow.weapon.as_ref().map(|weapon| format!(" with {}", weapon)).unwrap_or_default();
+        let mut attacker = Character { facing, defending: false, ..attacker };
+        let health = max(0, target.health - blow.damage);
+        if blow.hit {
+            println!("{} hits {}{} for {} damage, {} has {} health left", attacker.name, target.name, with, blow.damage, target.name, health);
+        } else {
+            println!("{} attacks {}{} and misses", attacker.name, target.name, with);
+        }
+
+        // fighting with a weapon is practice, and every blow landed is experience
+        if blow.weapon.is_some() {
+            attacker = progress::practise(&attacker, Skill::Swordsmanship);
+        }
+        if blow.hit {
+            let kill = if health == 0 { progress::KILL_EXPERIENCE } else { 0 };
+            attacker = progress::gain(&attacker, blow.damage as u32 * progress::DAMAGE_EXPERIENCE + kill);
+        }
+        self.remove_named_character(attacker.name.clone());
+        self.add_character(attacker);
+        if !blow.hit {
+            return;
+        }
+
+        self.remove_named_character(target.name.clone());
+        if health > 0 {
+            // claws and teeth leave poison in the wound
+            let target = if blow.weapon.is_none() { status::afflict(&target, EffectKind::Poisoned, status::POISON_TURNS) } else { target };
+            self.add_character(Character { health, ..target });
+            return;
+        }
+        let cause = format!("slain by {}", attacker_name);
+        self.die(Character { health, ..target }, cause);
+    }
+
+    // monsters strike at any player standing next to them
+    fn monsters_attack(&mut self) {
+        let players: Vec<Character> = self.characters().filter(|character| character.character_type == CharacterType::Player).cloned().collect();
+        let monsters: Vec<Character> = self.characters().filter(|character| character.character_type != CharacterType::Player).cloned().collect();
+        for monster in monsters.iter().filter(|monster| monster.health > 0) {
+            if monster.in_safe_zone() {
+                continue;
+            }
+            for player in players.iter().filter(|player| combat::adjacent(monster, player).is_some() && !player.in_safe_zone()) {
+                self.attack(&monster.name, &player.name);
+            }
+        }
+    }
+
+    // a character already taken out of the world dies: it leaves a corpse with its bag where it fell,
+    // and the player comes back at once while a monster waits its turn at a spawn point, see death.rs
+    fn die(&mut self, character: Character, cause: String) {
+        println!("{} dies", character.name);
+        if character.depth == 0 {
+            heatmap::add(&mut self.tallies.deaths, character.x_position, character.y_position);
+        }
+        let corpse = Corpse::of(&character);
+        let items = corpse.bag.len();
+        self.entities.push(GameEntity::Corpse(corpse));
+
+        let fate = if character.character_type == CharacterType::Player {
+            let (x, y) = self.respawn_point;
+            self.add_character(death::revive_player(&character, &self.game_map, x, y));
+            Fate::Respawned(x, y)
+        } else if let Some(&(x, y)) = self.spawn_points(SpawnKind::Monster).choose(&mut rand::thread_rng()) {
+            let revived = death::revive(&character, &self.game_map, x, y);
+            self.respawns.push(Respawn { character: revived, turns: death::MONSTER_RESPAWN_TURNS });
+            Fate::Returns(death::MONSTER_RESPAWN_TURNS)
+        } else {
+            Fate::Gone
+        };
+        self.deaths.push(DeathEvent {
+            name: character.name.clone(),
+            character_type: character.character_type.clone(),
+            cause,
+            x_position: character.x_position,
+            y_position: character.y_position,
+            depth: character.depth,
+            level: character.progress.level(),
+            items,
+            fate,
         });
-        player
+    }
+
+    // the corpses lying at x,y on a level
+    fn corpses_at(&self, x: i32, y: i32, depth: i32) -> impl Iterator<Item = &Corpse> {
+        self.entities.iter().filter_map(move |entity| match entity {
+            GameEntity::Corpse(corpse) if (corpse.x_position, corpse.y_position, corpse.depth) == (x, y, depth) => Some(corpse),
+            _ => None,
+        })
+    }
+
+    // a turn closer for every dead monster, those whose time has come are back
+    fn bring_back(&mut self) {
+        let (back, waiting): (Vec<Respawn>, Vec<Respawn>) = self.respawns.iter()
+            .map(|respawn| Respawn { turns: respawn.turns - 1, ..respawn.clone() })
+            .partition(|respawn| respawn.turns <= 0);
+        self.respawns = waiting;
+        for respawn in back {
+            println!("{} is back at ({}, {})", respawn.character.name, respawn.character.x_position, respawn.character.y_position);
+            self.add_character(respawn.character);
+        }
+    }
+
+    // every death since the last report, the player's with a summary
+    fn report_deaths(&mut self) {
+        for event in std::mem::take(&mut self.deaths) {
+            if event.character_type == CharacterType::Player {
+                println!("=== {} has died ===", event.name);
+                println!("{}", event);
+                println!("the corpse at ({}, {}) still holds everything that was carried", event.x_position, event.y_position);
+            } else {
+                println!("{}", event);
+            }
+        }
+    }
+
+    // move the player along the stairs they are standing on
+    fn take_stairs(&mut self, stairs: TerrainType) {
+        let Some(player) = self.search_for_player() else {
+            println!("Player not found");
+            return;
+        };
+        let (x, y) = (player.x_position, player.y_position);
+        if player.get_tile(x, y).terrain_type != stairs {
+            println!("{} is not standing on {:?}", player.name, stairs);
+            return;
+        }
+        let destination = self.stairs.get(&(player.depth, x, y))
+            .and_then(|&(depth, x, y)| self.level(depth).map(|game_map| (depth, x, y, game_map.clone())));
+        match destination {
+            Some((depth, x, y, game_map)) => {
+                println!("{} takes the stairs to depth {} at ({}, {})", player.name, depth, x, y);
+                // out of the world first, so the player's memory of the map is not shared
+                self.remove_named_character(player.name.clone());
+                let new_player = Character {
+                    x_position: x,
+                    y_position: y,
+                    depth,
+                    game_map,
+                    ..player
+                }.look_around();
+                self.add_character(new_player);
+            }
+            None => println!("The stairs at ({}, {}) lead nowhere", x, y),
+        }
     }
 
     pub fn remove_entity(&mut self, entity_to_remove: &GameEntity) {
@@ -951,7 +1913,7 @@ impl World {
     }
     // add item to items
     pub fn add_item(&mut self, item: Item) {
-        let entity = GameEntity::Items(Items { items: vec![item] });
+        let entity = GameEntity::Items(Items { items: vec![item], game_map: self.game_map.clone() });
         self.entities.push(entity);
     }
 
@@ -997,7 +1959,7 @@ impl World {
     }
 
     fn search_for_named_character(&self, name: String) -> Option<Character> {
-        let character = self.entities.iter().find_map(|entity| {
+        self.entities.iter().find_map(|entity| {
             match entity {
                 GameEntity::Character(character) => {
                     if character.name == name {
@@ -1008,8 +1970,7 @@ impl World {
                 }
                 _ => None,
             }
-        });
-        character
+        })
     }
 
     // remove character by name
@@ -1026,21 +1987,19 @@ impl World {
     // list the entities that are characters
     pub fn list_characters(&self) {
         for entity in &self.entities {
-            match entity {
-                GameEntity::Character(character) => {
-                    println!("{}", character);
-                }
-                _ => (),
+            if let GameEntity::Character(character) = entity {
+                println!("{}", character);
             }
         }
     }
 
+    // items appear on the item spawn points
     pub fn scatter_items(&mut self, num_items: i32) {
         let mut rng = rand::thread_rng();
+        let spawn_points = self.spawn_points(SpawnKind::Item);
 
         for _ in 0..num_items {
-            let x = rng.gen_range(0..self.width);
-            let y = rng.gen_range(0..self.height);
+            let Some(&(x, y)) = spawn_points.choose(&mut rng) else { return };
             let item = Item::new(ItemType::Food, "Apple", 10, x, y);
 
             self.items_by_name.insert(item.name.clone(), item.clone());
@@ -1048,6 +2007,16 @@ impl World {
         }
     }
 
+    // the overworld spawn points of one kind, in order of position
+    pub fn spawn_points(&self, kind: SpawnKind) -> Vec<(i32, i32)> {
+        zones::spawn_points(&self.game_map, kind)
+    }
+
+    // whether the overworld at x,y is safe, wild or dangerous
+    pub fn zone_at(&self, x: i32, y: i32) -> Zone {
+        self.game_map.get_tile(x, y).zone
+    }
+
     pub fn find_item_by_name(&self, name: &str) -> Option<&Item> {
         self.items_by_name.get(name)
     }
@@ -1062,14 +2031,41 @@ impl World {
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let config = WorldGenConfig::from_args(&args);
+    // print the seed so a reported world can be generated again
+    println!("World seed: {} size: {}x{}", config.seed, config.width, config.height);
+
+    // usage: rust_rpg --theme name [--palette file]
+    if let Some(name) = arg_value(&args, "--theme") {
+        if let Err(e) = palette::use_theme(name, arg_value(&args, "--palette").map(|file| file.as_str())) {
+            println!("{}", e);
+        }
+    }
+
+    let game_map = GameMap::new(config);
+    let (underground, stairs) = dungeon::generate_underground(&game_map);
+
+    // the player starts in a settlement and the troll somewhere monsters roam
+    let (player_x, player_y) = zones::spawn_points(&game_map, SpawnKind::Character).first().copied().unwrap_or((700, 500));
+    let (troll_x, troll_y) = zones::spawn_points(&game_map, SpawnKind::Monster).first().copied().unwrap_or((800, 900));
+    let respawn_point = respawn_point(&args, &game_map, (player_x, player_y));
+
     let mut world = World {
-        height: 2048,
-        width: 2048,
+        height: game_map.height,
+        width: game_map.width,
         entities: Vec::new(),
-        game_map: GameMap::new(2048, 2048),
+        game_map,
+        underground,
+        stairs,
         // other world state...
         items_by_name: HashMap::new(),
         items_by_position: HashMap::new(),
+        recorder: None,
+        tallies: Tallies::default(),
+        deaths: Vec::new(),
+        respawns: Vec::new(),
+        respawn_point,
     };
 
     let player = Character {
@@ -1078,13 +2074,23 @@ fn main() {
         energy: 1000,
         hydration: 1000,
         health: 100,
+        mana: 50,
+        attributes: Attributes::new(10, 10, 10),
+        progress: Progress::default(),
         attack: 10,
         defense: 5,
-        x_position: 700,
-        y_position: 500,
+        x_position: player_x,
+        y_position: player_y,
+        depth: 0,
         facing: Direction::North,
+        defending: false,
         bag: Vec::new(),
-    };
+        game_map: world.game_map.clone(),
+        explored: Arc::new(Explored::default()),
+        visible: Arc::new(HashSet::new()),
+        visited: Arc::new(Explored::default()),
+        effects: Arc::new(Vec::new()),
+    }.look_around();
 
     // create a new character
     let troll = Character {
@@ -1093,13 +2099,23 @@ fn main() {
         energy: 1000,
         hydration: 1000,
         health: 150,
+        mana: 15,
+        attributes: Attributes::new(20, 6, 3),
+        progress: Progress::default(),
         attack: 5,
         defense: 2,
-        x_position: 800,
-        y_position: 900,
+        x_position: troll_x,
+        y_position: troll_y,
+        depth: 0,
         facing: Direction::East,
+        defending: false,
         bag: Vec::new(),
-    };
+        game_map: world.game_map.clone(),
+        explored: Arc::new(Explored::default()),
+        visible: Arc::new(HashSet::new()),
+        visited: Arc::new(Explored::default()),
+        effects: Arc::new(Vec::new()),
+    }.look_around();
 
     world.add_character(player);
     world.add_character(troll);
@@ -1114,6 +2130,10 @@ fn main() {
             y_position: 500,
         };
         world.add_item_to_characters_bag(item, "PlayerOne");
+        let sword = Item::new(ItemType::Weapon, "Sword", 15, 500, 500);
+        world.add_item_to_characters_bag(sword, "PlayerOne");
+        let potion = Item::new(ItemType::Potion, "Troll Blood", 10, 500, 500);
+        world.add_item_to_characters_bag(potion, "PlayerOne");
     }
     {
         let item = Item {
@@ -1128,10 +2148,172 @@ fn main() {
 
     world.scatter_items(10000);
 
-    world.game_map.generate_map_image("elevation_map.png");
+    if let Err(e) = map_export::export(&world, "elevation_map.png", &MapLayers::default(), &MapStyle::default(), None) {
+        println!("Failed to save elevation_map.png: {}", e);
+    }
+    println!("{}", world.game_map.cache_stats());
 
     world.list_characters();
 
+    // a headless run simulates a number of ticks without waiting for commands, and can record them
+    // usage: rust_rpg --ticks N [--record out.gif|frames_dir]
+    if let Some(ticks) = arg_value(&args, "--ticks").and_then(|value| value.parse().ok()) {
+        let record = arg_value(&args, "--record");
+        if record.is_some() {
+            world.recorder = Some(Recorder::default());
+        }
+        world.simulate(ticks);
+        if let (Some(path), Some(recorder)) = (record, &world.recorder) {
+            match recorder.save(&world.game_map, path) {
+                Ok(()) => println!("Saved {} ticks to {}", recorder.tick_count(), path),
+                Err(e) => println!("Failed to save the recording to {}: {}", path, e),
+            }
+        }
+        return;
+    }
+
     world.command_loop();
 }
 
+// the value after a command line flag
+fn arg_value<'a>(args: &'a [String], flag: &str) -> Option<&'a String> {
+    args.iter().position(|arg| arg == flag).and_then(|index| args.get(index + 1))
+}
+
+// where the player comes back after dying, the safe ground nearest the point asked for, or where it started
+// usage: rust_rpg --respawn x,y
+fn respawn_point(args: &[String], map: &GameMap, start: (i32, i32)) -> (i32, i32) {
+    let Some(value) = arg_value(args, "--respawn") else { return start };
+    let asked = value.split_once(',').and_then(|(x, y)| Some((x.trim().parse().ok()?, y.trim().parse().ok()?)));
+    let Some((x, y)) = asked else {
+        println!("Invalid respawn point {}, expected x,y, respawning at the start", value);
+        return start;
+    };
+    match zones::nearest_safe(map, x, y) {
+        Some(point) => {
+            if point != (x, y) {
+                println!("({}, {}) is not safe ground, respawning at ({}, {}) instead", x, y, point.0, point.1);
+            }
+            point
+        }
+        None => {
+            println!("No safe ground near ({}, {}), respawning at the start", x, y);
+            start
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use world_gen::ErosionConfig;
+
+    // a world small enough to build quickly
+    pub(crate) fn small_config(seed: u32) -> WorldGenConfig {
+        WorldGenConfig { width: 128, height: 128, falloff_margin: 40.0, ..WorldGenConfig::with_seed(seed) }
+    }
+
+    // a character standing at x,y on a map, with nothing in its bag
+    pub(crate) fn character(name: &str, character_type: CharacterType, map: &GameMap, x: i32, y: i32) -> Character {
+        Character {
+            character_type,
+            name: name.to_string(),
+            energy: 1000,
+            hydration: 1000,
+            health: 100,
+            mana: 50,
+            attributes: Attributes::new(10, 10, 10),
+            progress: Progress::default(),
+            attack: 10,
+            defense: 5,
+            x_position: x,
+            y_position: y,
+            depth: map.depth(),
+            facing: Direction::North,
+            defending: false,
+            bag: Vec::new(),
+            game_map: map.clone(),
+            explored: Arc::new(Explored::default()),
+            visible: Arc::new(HashSet::new()),
+            visited: Arc::new(Explored::default()),
+            effects: Arc::new(Vec::new()),
+        }.look_around()
+    }
+
+    // a world with its levels underground and no one in it yet
+    pub(crate) fn world(config: WorldGenConfig) -> World {
+        let game_map = GameMap::new(config);
+        let (underground, stairs) = dungeon::generate_underground(&game_map);
+        World {
+            height: game_map.height,
+            width: game_map.width,
+            entities: Vec::new(),
+            game_map,
+            underground,
+            stairs,
+            items_by_name: HashMap::new(),
+            items_by_position: HashMap::new(),
+            recorder: None,
+            tallies: Tallies::default(),
+            deaths: Vec::new(),
+            respawns: Vec::new(),
+            respawn_point: (0, 0),
+        }
+    }
+
+    fn tiles(config: WorldGenConfig) -> Vec<Tile> {
+        let map = GameMap::new(config.clone());
+        (0..config.height).flat_map(|y| (0..config.width).map(move |x| (x, y)))
+            .map(|(x, y)| map.get_tile(x, y))
+            .collect()
+    }
+
+    #[test]
+    fn same_config_builds_the_same_world() {
+        assert_eq!(tiles(small_config(42)), tiles(small_config(42)));
+    }
+
+    #[test]
+    fn same_config_erodes_the_same_way() {
+        let eroded = || WorldGenConfig { erosion: Some(ErosionConfig { droplets: 2000, ..ErosionConfig::default() }), ..small_config(42) };
+        assert_eq!(tiles(eroded()), tiles(eroded()));
+    }
+
+    #[test]
+    fn different_seed_builds_a_different_world() {
+        assert_ne!(tiles(small_config(42)), tiles(small_config(43)));
+    }
+
+    #[test]
+    fn looking_around_marks_the_same_memory_when_no_one_else_holds_it() {
+        let map = GameMap::new(small_config(42));
+        let player = character("PlayerOne", CharacterType::Player, &map, 64, 64);
+        let memory = Arc::as_ptr(&player.explored);
+        let player = Character { x_position: 65, ..player }.look_around();
+        assert_eq!(Arc::as_ptr(&player.explored), memory);
+        assert!(player.explored.contains(0, 65, 64));
+    }
+
+    #[test]
+    fn fog_memory_persists_across_levels() {
+        let mut world = world(WorldGenConfig { width: 256, height: 256, falloff_margin: 80.0, dungeon_levels: 2, ..WorldGenConfig::with_seed(1) });
+        let (_, x, y) = *world.stairs.keys().filter(|(depth, _, _)| *depth == 0).min().expect("stairs down from the overworld");
+        let player = character("PlayerOne", CharacterType::Player, &world.game_map, x, y);
+        let seen_above: Vec<(i32, i32)> = player.visible.iter().copied().collect();
+        world.add_character(player);
+
+        world.take_stairs(TerrainType::StairsDown);
+        let below = world.search_for_player().unwrap();
+        assert_eq!(below.depth, 1);
+        assert!(seen_above.iter().all(|&(x, y)| below.explored.contains(0, x, y)));
+        let seen_below: Vec<(i32, i32)> = below.visible.iter().copied().collect();
+        assert!(seen_below.iter().all(|&(x, y)| below.explored.contains(1, x, y)));
+
+        world.take_stairs(TerrainType::StairsUp);
+        let back = world.search_for_player().unwrap();
+        assert_eq!((back.depth, back.x_position, back.y_position), (0, x, y));
+        assert!(seen_above.iter().all(|&(x, y)| back.explored.contains(0, x, y)));
+        assert!(seen_below.iter().all(|&(x, y)| back.explored.contains(1, x, y)));
+        assert_eq!(back.seen(x, y), Seen::Visible);
+    }
+}
@@ -0,0 +1,214 @@
+// Path: src/map_export.rs
+
+use image::{ImageBuffer, ImageResult, Rgb, RgbImage};
+use imageproc::drawing::{draw_filled_circle_mut, draw_filled_rect_mut, draw_line_segment_mut};
+use imageproc::rect::Rect;
+
+use rayon::prelude::*;
+
+use crate::fov::Seen;
+use crate::palette;
+use crate::relief::{self, MapStyle};
+use crate::{Character, Color, Direction, GameEntity, GameObject, TerrainType, World};
+
+// ===========================================================================
+// Map export
+// the map image is built up in layers, terrain at the bottom, drawn in one of the relief styles, then items,
+// then characters and the names of places on top. any layer can be left out.
+// names are drawn with a small built in font, so no font file is needed.
+// the map can be drawn as one character knows it, leaving out what it has never seen,
+// dimming what it remembers and only showing items and characters in sight.
+
+// how long the arms of a character's V are, in pixels
+const V_LENGTH: f32 = 6.0;
+const CHARACTER_RADIUS: i32 = 2;
+// each pixel of a letter is drawn this many pixels across
+const LETTER_SCALE: i32 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MapLayers {
+    pub terrain: bool,
+    pub items: bool,
+    pub characters: bool,
+    pub labels: bool,
+}
+
+impl Default for MapLayers {
+    fn default() -> Self {
+        MapLayers { terrain: true, items: true, characters: true, labels: true }
+    }
+}
+
+impl MapLayers {
+    // only the named layers, such as "terrain,items", None if a name is not a layer
+    pub fn from_names(names: &str) -> Option<Self> {
+        let mut layers = MapLayers { terrain: false, items: false, characters: false, labels: false };
+        for name in names.split(',') {
+            match name.trim() {
+                "terrain" => layers.terrain = true,
+                "items" => layers.items = true,
+                "characters" => layers.characters = true,
+                "labels" => layers.labels = true,
+                _ => return None,
+            }
+        }
+        Some(layers)
+    }
+}
+
+// draw the overworld with the chosen layers, the terrain in the given style, and save it as filename,
+// seen through the eyes of a character on the overworld when there is one
+pub fn export(world: &World, filename: &str, layers: &MapLayers, style: &MapStyle, view: Option<&Character>) -> ImageResult<()> {
+    let game_map = &world.game_map;
+    let theme = palette::current();
+    let mut img: RgbImage = if layers.terrain {
+        let mut img = game_map.terrain_image();
+        relief::apply(&mut img, game_map, style);
+        img
+    } else {
+        ImageBuffer::from_pixel(game_map.width as u32, game_map.height as u32, Color::BLACK.to_rgb())
+    };
+    let view = view.filter(|character| character.depth == 0);
+    let seen = |x: i32, y: i32| view.map_or(Seen::Visible, |character| character.seen(x, y));
+    if view.is_some() {
+        img.enumerate_pixels_mut().par_bridge().for_each(|(x, y, pixel)| {
+            let color = Color { r: pixel[0], g: pixel[1], b: pixel[2] };
+            *pixel = seen(x as i32, y as i32).shade(color).unwrap_or(Color::BLACK).to_rgb();
+        });
+    }
+
+    if layers.items {
+        for ((x, y), item) in &world.items_by_position {
+            if in_image(&img, *x, *y) && seen(*x, *y) == Seen::Visible && game_map.get_tile(*x, *y).terrain_type != TerrainType::Water {
+                img.put_pixel(*x as u32, *y as u32, theme.item(&item.item_type).to_rgb());
+            }
+        }
+    }
+
+    if layers.characters {
+        for entity in &world.entities {
+            if let GameEntity::Character(character) = entity {
+                if character.depth == 0 && seen(character.x_position, character.y_position) == Seen::Visible {
+                    draw_character(&mut img, character, theme.character(&character.character_type));
+                }
+            }
+        }
+    }
+
+    if layers.labels {
+        // a character only knows the names of places it has seen
+        for settlement in game_map.settlements().iter().filter(|settlement| seen(settlement.x, settlement.y) != Seen::Unexplored) {
+            draw_label(&mut img, &settlement.name, settlement.x, settlement.y + settlement.size.radius() + 2);
+        }
+        for region in game_map.regions().all().iter().filter(|region| seen(region.x, region.y) != Seen::Unexplored) {
+            draw_label(&mut img, &region.name, region.x, region.y);
+        }
+    }
+
+    img.save(filename)
+}
+
+fn in_image(img: &RgbImage, x: i32, y: i32) -> bool {
+    x >= 0 && y >= 0 && x < img.width() as i32 && y < img.height() as i32
+}
+
+// a dot with a V in front of it pointing the way the character faces
+fn draw_character(img: &mut RgbImage, character: &Character, color: Color) {
+    let color = color.to_rgb();
+    let (x, y) = (character.x_position, character.y_position);
+    draw_filled_circle_mut(img, (x, y), CHARACTER_RADIUS, color);
+
+    // the tip is out in front, the arms trail back either side of it
+    let (tip_x, tip_y) = step((x as f32, y as f32), character.facing, V_LENGTH);
+    let back = character.facing.opposite();
+    for side in [character.facing.turn_left(), character.facing.turn_right()] {
+        let (back_x, back_y) = back.get_offset();
+        let (side_x, side_y) = side.get_offset();
+        let arm = ((back_x + side_x) as f32, (back_y + side_y) as f32);
+        let length = (arm.0 * arm.0 + arm.1 * arm.1).sqrt();
+        let end = (tip_x + arm.0 / length * V_LENGTH, tip_y + arm.1 / length * V_LENGTH);
+        draw_line_segment_mut(img, (tip_x, tip_y), end, color);
+        draw_line_segment_mut(img, (tip_x + 1.0, tip_y), (end.0 + 1.0, end.1), color);
+    }
+}
+
+fn step((x, y): (f32, f32), direction: Direction, distance: f32) -> (f32, f32) {
+    let (dx, dy) = direction.get_offset();
+    (x + dx as f32 * distance, y + dy as f32 * distance)
+}
+
+// text centred on x, with its top at y, in white with a dark shadow so it shows on any terrain
+fn draw_label(img: &mut RgbImage, text: &str, x: i32, y: i32) {
+    let advance = (LETTER_WIDTH + 1) * LETTER_SCALE;
+    let left = x - text.chars().count() as i32 * advance / 2;
+    for (offset, color) in [(1, Color::BLACK), (0, Color::WHITE)] {
+        for (index, letter) in text.chars().enumerate() {
+            draw_letter(img, letter, left + index as i32 * advance + offset, y + offset, color.to_rgb());
+        }
+    }
+}
+
+fn draw_letter(img: &mut RgbImage, letter: char, x: i32, y: i32, color: Rgb<u8>) {
+    let Some(rows) = letter_rows(letter) else { return };
+    for (row, bits) in rows.iter().enumerate() {
+        for column in 0..LETTER_WIDTH {
+            if bits & (1 << (LETTER_WIDTH - 1 - column)) != 0 {
+                let (px, py) = (x + column * LETTER_SCALE, y + row as i32 * LETTER_SCALE);
+                if in_image(img, px, py) {
+                    draw_filled_rect_mut(img, Rect::at(px, py).of_size(LETTER_SCALE as u32, LETTER_SCALE as u32), color);
+                }
+            }
+        }
+    }
+}
+
+// ===========================================================================
+// the font, each letter is five rows of three pixels, capitals only
+
+const LETTER_WIDTH: i32 = 3;
+
+fn letter_rows(letter: char) -> Option<[u8; 5]> {
+    let rows = match letter.to_ascii_uppercase() {
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b110, 0b001, 0b010, 0b100, 0b111],
+        '3' => [0b110, 0b001, 0b010, 0b001, 0b110],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b110, 0b001, 0b110],
+        '6' => [0b011, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b110],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '\'' => [0b010, 0b010, 0b000, 0b000, 0b000],
+        ' ' => [0; 5],
+        _ => return None,
+    };
+    Some(rows)
+}
@@ -0,0 +1,110 @@
+// Path: src/naming.rs
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+// ===========================================================================
+// Names
+// places are named from a small grammar. each rule turns a <symbol> into one of
+// its choices, picked at random, until only letters are left.
+// the roots of names are strings of syllables, each one a consonant onset,
+// a vowel and sometimes a closing consonant.
+// names come from the world's random numbers, so the same seed names the same places.
+
+const ONSETS: [&str; 18] = ["b", "d", "f", "g", "h", "k", "l", "m", "n", "r", "s", "t", "v", "w", "br", "dr", "st", "th"];
+const VOWELS: [&str; 8] = ["a", "e", "i", "o", "u", "ae", "ea", "ou"];
+const CODAS: [&str; 8] = ["n", "r", "l", "s", "th", "m", "ck", "nd"];
+
+// the grammar, <root> and <short> are made up words and come out with a capital letter,
+// <syllable> is filled in from the letter tables
+const RULES: [(&str, &[&str]); 10] = [
+    ("town", &["<root>", "<root><town_end>"]),
+    ("town_end", &["ton", "by", "wick", "ford", "ham", "stead"]),
+    ("range", &["the <root><range_end>", "the <root> Mountains", "the <root> Peaks"]),
+    ("range_end", &["spine", "crags", "horn", "fells", "teeth"]),
+    ("lake", &["Lake <short>", "<root> Water", "<root>mere"]),
+    ("bay", &["<root> Bay", "the Bay of <short>", "<root> Sound"]),
+    ("island", &["the Isle of <short>", "<root> Isle", "<root>holm"]),
+    ("land", &["<root>", "<root>land", "the <root> Reach"]),
+    ("root", &["<syllable><syllable>", "<syllable><syllable>", "<syllable><syllable><syllable>"]),
+    ("short", &["<syllable>", "<syllable><syllable>"]),
+];
+
+// what sort of place a name is for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NameKind {
+    Town,
+    MountainRange,
+    Lake,
+    Bay,
+    Island,
+    Land,
+}
+
+impl NameKind {
+    fn symbol(&self) -> &str {
+        match self {
+            NameKind::Town => "town",
+            NameKind::MountainRange => "range",
+            NameKind::Lake => "lake",
+            NameKind::Bay => "bay",
+            NameKind::Island => "island",
+            NameKind::Land => "land",
+        }
+    }
+}
+
+// a new name for a place of the given kind
+pub fn name(rng: &mut StdRng, kind: NameKind) -> String {
+    expand(rng, kind.symbol())
+}
+
+fn expand(rng: &mut StdRng, symbol: &str) -> String {
+    if symbol == "syllable" {
+        return syllable(rng);
+    }
+    let choices = RULES.iter()
+        .find(|(name, _)| *name == symbol)
+        .map(|(_, choices)| *choices)
+        .unwrap_or(&[]);
+    let Some(choice) = choices.choose(rng) else {
+        return String::new();
+    };
+
+    // copy the letters, expanding each <symbol> as it is reached
+    let mut result = String::new();
+    let mut rest = *choice;
+    while let Some(start) = rest.find('<') {
+        result.push_str(&rest[..start]);
+        let end = start + rest[start..].find('>').unwrap_or(rest.len() - start - 1);
+        let inner = &rest[start + 1..end];
+        let expanded = expand(rng, inner);
+        if inner == "root" || inner == "short" {
+            result.push_str(&capitalize(&expanded));
+        } else {
+            result.push_str(&expanded);
+        }
+        rest = &rest[(end + 1).min(rest.len())..];
+    }
+    result.push_str(rest);
+    result
+}
+
+fn syllable(rng: &mut StdRng) -> String {
+    let mut syllable = String::new();
+    syllable.push_str(ONSETS.choose(rng).unwrap());
+    syllable.push_str(VOWELS.choose(rng).unwrap());
+    if rng.gen_bool(0.3) {
+        syllable.push_str(CODAS.choose(rng).unwrap());
+    }
+    syllable
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,297 @@
+// Path: src/palette.rs
+
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, RwLock};
+use once_cell::sync::Lazy;
+
+use crate::relief;
+use crate::{CharacterType, Color, ItemType, TerrainType};
+
+// ===========================================================================
+// Palettes
+// the colours everything is drawn in come from a theme, so the map images, the terminal map
+// and the other renderers all agree, and a theme can be swapped for one that suits
+// colour blindness or printing.
+// themes are read from a data file of named colours, see assets/palettes.txt, which is also
+// built into the game so the themes there are always there.
+// one theme is in use at a time, for every renderer at once.
+
+pub const DEFAULT_THEME: &str = "classic";
+// the themes the game comes with
+const BUILT_IN: &str = include_str!("../assets/palettes.txt");
+// what a theme leaves out is drawn in, loud enough to notice
+const MISSING: Color = Color::FUCHSIA;
+
+static CURRENT: Lazy<RwLock<Arc<Theme>>> = Lazy::new(|| {
+    let palette = Palette::built_in();
+    let theme = palette.theme(DEFAULT_THEME).cloned().unwrap_or_default();
+    RwLock::new(Arc::new(theme))
+});
+
+// the theme everything is being drawn in, held onto by a renderer for the length of a drawing
+pub fn current() -> Arc<Theme> {
+    CURRENT.read().map(|theme| theme.clone()).unwrap_or_default()
+}
+
+// draw everything in theme from now on
+fn set(theme: Theme) {
+    if let Ok(mut current) = CURRENT.write() {
+        *current = Arc::new(theme);
+    }
+}
+
+// switch to the named theme, from a palette file or the built in themes
+pub fn use_theme(name: &str, filename: Option<&str>) -> Result<(), String> {
+    let palette = match filename {
+        Some(filename) => Palette::load(filename)?,
+        None => Palette::built_in(),
+    };
+    match palette.theme(name) {
+        Some(theme) => {
+            set(theme.clone());
+            Ok(())
+        }
+        None => Err(format!("No theme called {}, the themes are {}", name, palette.theme_names().join(", "))),
+    }
+}
+
+// a colour for a terrain, and whether it darkens with height
+#[derive(Clone, Copy, Debug)]
+struct Paint {
+    color: Color,
+    shaded: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Theme {
+    pub name: String,
+    terrain: HashMap<TerrainType, Paint>,
+    // by the name of the type, as in the data file
+    characters: HashMap<String, Color>,
+    items: HashMap<String, Color>,
+    // the stops of the hypsometric tints, land from the shore at 0 to the highest ground at 1,
+    // sea from the deepest at 0 to the shallows at 1
+    heights: Vec<(f64, Color)>,
+    depths: Vec<(f64, Color)>,
+}
+
+impl Theme {
+    // the colour of a terrain at a height
+    pub fn terrain(&self, terrain_type: TerrainType, elevation: i32) -> Color {
+        match self.terrain.get(&terrain_type) {
+            Some(paint) if paint.shaded => {
+                // darker the higher it is, black at 40 and above
+                let shade = (elevation as f32 / 100.0 * 255.0).clamp(0.0, 100.0) as u32;
+                let darken = |channel: u8| (channel as u32 * (100 - shade) / 100) as u8;
+                Color { r: darken(paint.color.r), g: darken(paint.color.g), b: darken(paint.color.b) }
+            }
+            Some(paint) => paint.color,
+            None => MISSING,
+        }
+    }
+
+    pub fn character(&self, character_type: &CharacterType) -> Color {
+        self.characters.get(&format!("{:?}", character_type)).copied().unwrap_or(MISSING)
+    }
+
+    pub fn item(&self, item_type: &ItemType) -> Color {
+        self.items.get(&format!("{:?}", item_type)).copied().unwrap_or(MISSING)
+    }
+
+    // the colour of land a share of the way from the shore to the highest ground
+    pub fn height_tint(&self, share: f64) -> Color {
+        tint(&self.heights, share)
+    }
+
+    // the colour of sea a share of the way from the deepest to the shallows
+    pub fn depth_tint(&self, share: f64) -> Color {
+        tint(&self.depths, share)
+    }
+}
+
+fn tint(stops: &[(f64, Color)], share: f64) -> Color {
+    if stops.is_empty() { MISSING } else { relief::ramp(stops, share) }
+}
+
+// the named colours and themes of a data file
+#[derive(Clone, Debug, Default)]
+pub struct Palette {
+    colors: HashMap<String, Color>,
+    themes: Vec<Theme>,
+}
+
+impl Palette {
+    pub fn built_in() -> Self {
+        // a mistake in the built in file leaves everything drawn in the missing colour
+        Palette::parse(BUILT_IN, "the built in palettes").unwrap_or_default()
+    }
+
+    pub fn load(filename: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(filename).map_err(|e| format!("Failed to read {}: {}", filename, e))?;
+        Palette::parse(&text, filename)
+    }
+
+    fn parse(text: &str, source: &str) -> Result<Self, String> {
+        let mut palette = Palette::default();
+        for (number, whole_line) in text.lines().enumerate() {
+            let line = strip_comment(whole_line);
+            if line.is_empty() {
+                continue;
+            }
+            // the whole line, as a bad colour is taken for the start of a comment
+            let bad = || format!("{} line {}: {}", source, number + 1, whole_line.trim());
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words[..] {
+                ["colour", name, hex] => {
+                    let color = Color::from_hex(hex).ok_or_else(bad)?;
+                    palette.colors.insert(name.to_string(), color);
+                }
+                ["theme", name] => palette.themes.push(Theme { name: name.to_string(), ..Theme::default() }),
+                [kind, name, color, ref rest @ ..] => {
+                    let color = palette.color(color).ok_or_else(bad)?;
+                    let theme = palette.themes.last_mut().ok_or_else(bad)?;
+                    match (kind, rest) {
+                        ("terrain", []) | ("terrain", ["shaded"]) => {
+                            let terrain_type = name.parse::<TerrainType>().map_err(|_| bad())?;
+                            theme.terrain.insert(terrain_type, Paint { color, shaded: !rest.is_empty() });
+                        }
+                        ("character", []) => { theme.characters.insert(name.to_string(), color); }
+                        ("item", []) => { theme.items.insert(name.to_string(), color); }
+                        ("height", []) | ("depth", []) => {
+                            let stops = if kind == "height" { &mut theme.heights } else { &mut theme.depths };
+                            // the stops go up from 0 to 1
+                            let share = name.parse::<f64>().ok()
+                                .filter(|share| (0.0..=1.0).contains(share))
+                                .filter(|&share| stops.last().is_none_or(|&(last, _)| share > last))
+                                .ok_or_else(bad)?;
+                            stops.push((share, color));
+                        }
+                        _ => return Err(bad()),
+                    }
+                }
+                _ => return Err(bad()),
+            }
+        }
+        Ok(palette)
+    }
+
+    // a colour by name or as #rrggbb
+    fn color(&self, name: &str) -> Option<Color> {
+        Color::from_hex(name).or_else(|| self.colors.get(name).copied())
+    }
+
+    pub fn theme(&self, name: &str) -> Option<&Theme> {
+        self.themes.iter().find(|theme| theme.name == name)
+    }
+
+    pub fn theme_names(&self) -> Vec<&str> {
+        self.themes.iter().map(|theme| theme.name.as_str()).collect()
+    }
+}
+
+// the line without its comment, a # starts one unless it starts a #rrggbb colour
+fn strip_comment(line: &str) -> &str {
+    let mut end = line.len();
+    for (index, _) in line.match_indices('#') {
+        let after_space = index == 0 || line[..index].ends_with(char::is_whitespace);
+        let is_color = after_space && Color::from_hex(line[index..].split_whitespace().next().unwrap_or("")).is_some();
+        if !is_color {
+            end = index;
+            break;
+        }
+    }
+    line[..end].trim()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_built_in_theme_tints_the_relief() {
+        let palette = Palette::built_in();
+        assert_eq!(palette.theme_names(), ["classic", "colourblind", "mono"]);
+        for theme in &palette.themes {
+            for share in [0.0, 0.3, 1.0] {
+                assert_ne!(theme.height_tint(share), MISSING, "{}", theme.name);
+                assert_ne!(theme.depth_tint(share), MISSING, "{}", theme.name);
+            }
+        }
+        let (classic, mono) = (palette.theme("classic").unwrap(), palette.theme("mono").unwrap());
+        assert_eq!(classic.height_tint(1.0), Color::SNOW);
+        assert_eq!(classic.depth_tint(0.0), Color::NAVY);
+        assert_ne!(classic.height_tint(0.5), mono.height_tint(0.5));
+        assert_ne!(classic.depth_tint(0.5), mono.depth_tint(0.5));
+    }
+
+    #[test]
+    fn tints_run_between_their_stops() {
+        let palette = Palette::parse("theme t\nheight 0 #000000\nheight 0.5 #646464\nheight 1 #ffffff\n", "test").unwrap();
+        let theme = palette.theme("t").unwrap();
+        assert_eq!(theme.height_tint(0.25), Color { r: 50, g: 50, b: 50 });
+        assert_eq!(theme.height_tint(2.0), Color::WHITE);
+        assert_eq!(theme.depth_tint(0.5), MISSING);
+    }
+
+    #[test]
+    fn tint_stops_must_go_up_from_0_to_1() {
+        assert!(Palette::parse("theme t\nheight 0.5 red\nheight 0.2 blue\n", "test").is_err());
+        assert!(Palette::parse("theme t\ndepth 1.5 red\n", "test").is_err());
+        assert!(Palette::parse("theme t\ndepth deep red\n", "test").is_err());
+        assert!(Palette::parse("theme t\nheight 0 red shaded\n", "test").is_err());
+    }
+
+    #[test]
+    fn themes_read_named_and_hex_colours() {
+        let text = "# a comment\ncolour moss #2e8b57 # a comment after a colour\n\ntheme t\nterrain Grass moss shaded\nterrain Road #303030\ncharacter Player #ffffff\nitem Food moss\n";
+        let palette = Palette::parse(text, "test").unwrap();
+        let theme = palette.theme("t").unwrap();
+        assert_eq!(theme.terrain(TerrainType::Road, 50), Color { r: 0x30, g: 0x30, b: 0x30 });
+        assert_eq!(theme.terrain(TerrainType::Grass, 0), Color { r: 0x2e, g: 0x8b, b: 0x57 });
+        assert_eq!(theme.terrain(TerrainType::Grass, 40), Color::BLACK);
+        assert_eq!(theme.character(&CharacterType::Player), Color::WHITE);
+        assert_eq!(theme.item(&ItemType::Food), Color { r: 0x2e, g: 0x8b, b: 0x57 });
+        assert_eq!(theme.item(&ItemType::Weapon), MISSING);
+        assert_eq!(theme.terrain(TerrainType::Water, 0), MISSING);
+    }
+
+    #[test]
+    fn malformed_lines_are_reported_with_their_number() {
+        let error = |text: &str| Palette::parse(text, "test").unwrap_err();
+        assert_eq!(error("theme t\nterrain Grass"), "test line 2: terrain Grass");
+        assert_eq!(error("theme t\nterrain Grass green shaded extra"), "test line 2: terrain Grass green shaded extra");
+        assert_eq!(error("colour green"), "test line 1: colour green");
+        assert_eq!(error("painting Grass #00ff00"), "test line 1: painting Grass #00ff00");
+        assert_eq!(error("theme"), "test line 1: theme");
+        // a colour before any theme
+        assert_eq!(error("terrain Grass #00ff00"), "test line 1: terrain Grass #00ff00");
+    }
+
+    #[test]
+    fn unknown_names_and_bad_colours_are_errors() {
+        let error = |text: &str| Palette::parse(text, "test").unwrap_err();
+        assert_eq!(error("theme t\nterrain Lava #ff0000"), "test line 2: terrain Lava #ff0000");
+        assert_eq!(error("theme t\nterrain Grass chartreuse"), "test line 2: terrain Grass chartreuse");
+        assert_eq!(error("colour moss #2e8b5"), "test line 1: colour moss #2e8b5");
+        assert_eq!(error("colour moss 2e8b57"), "test line 1: colour moss 2e8b57");
+        assert_eq!(error("colour moss #2e8b5g"), "test line 1: colour moss #2e8b5g");
+    }
+
+    #[test]
+    fn hex_colours() {
+        assert_eq!(Color::from_hex("#1e90ff"), Some(Color { r: 0x1e, g: 0x90, b: 0xff }));
+        assert_eq!(Color::from_hex("#1E90FF"), Some(Color { r: 0x1e, g: 0x90, b: 0xff }));
+        assert_eq!(Color::from_hex("#1e90ff").map(|color| color.to_hex()), Some("#1e90ff".to_string()));
+        for bad in ["", "#", "1e90ff", "#1e90f", "#1e90ff0", "#1e90fg", "#+1e90f", "#1é90f", "##1e90f"] {
+            assert_eq!(Color::from_hex(bad), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn comments_start_at_a_hash_that_is_not_a_colour() {
+        assert_eq!(strip_comment("terrain Road #303030 # grey"), "terrain Road #303030");
+        assert_eq!(strip_comment("# only a comment"), "");
+        assert_eq!(strip_comment("terrain Road gray#303030"), "terrain Road gray");
+    }
+}
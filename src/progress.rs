@@ -0,0 +1,223 @@
+// Path: src/progress.rs
+
+use std::collections::HashMap;
+
+use crate::attributes::Attribute;
+use crate::status::{self, EffectKind};
+use crate::Character;
+
+// ===========================================================================
+// Progress
+// characters get better as they go. experience comes from fighting and from setting foot
+// on ground they have never walked before, and every level brings points to raise attributes with.
+// skills improve on their own with use: swimming by fording, climbing by scrambling up and down
+// steep ground, foraging by eating what is found, and swordsmanship by fighting with a weapon.
+// a better skill makes the thing it is used for cheaper or more likely to work.
+
+// the experience to go from level n to n + 1 is n times this
+const EXPERIENCE_PER_LEVEL: u32 = 100;
+// attribute points gained on each new level
+const POINTS_PER_LEVEL: u32 = 2;
+// the experience for each new tile walked on, each point of damage dealt, and a kill on top
+pub const EXPLORE_EXPERIENCE: u32 = 1;
+pub const DAMAGE_EXPERIENCE: u32 = 1;
+pub const KILL_EXPERIENCE: u32 = 25;
+// a skill goes from level n to n + 1 after n + 1 times this much more practice
+const PRACTICE_PER_LEVEL: u32 = 5;
+const MAX_SKILL: u32 = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Skill {
+    Swimming,
+    Climbing,
+    Foraging,
+    Swordsmanship,
+}
+
+impl Skill {
+    pub const ALL: [Skill; 4] = [Skill::Swimming, Skill::Climbing, Skill::Foraging, Skill::Swordsmanship];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Skill::Swimming => "swimming",
+            Skill::Climbing => "climbing",
+            Skill::Foraging => "foraging",
+            Skill::Swordsmanship => "swordsmanship",
+        }
+    }
+}
+
+// a character's experience, level, points to spend and practice at each skill
+#[derive(Clone, Debug, PartialEq)]
+pub struct Progress {
+    experience: u32,
+    level: u32,
+    points: u32,
+    practice: HashMap<Skill, u32>,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Progress { experience: 0, level: 1, points: 0, practice: HashMap::new() }
+    }
+}
+
+impl Progress {
+    // the experience at which the next level is reached
+    fn next_level_at(&self) -> u32 {
+        EXPERIENCE_PER_LEVEL * self.level * (self.level + 1) / 2
+    }
+
+    pub fn level(&self) -> u32 {
+        self.level
+    }
+
+    // percent of the experience towards the next level lost, never a level
+    pub fn setback(&self, percent: u32) -> Self {
+        let level_start = EXPERIENCE_PER_LEVEL * (self.level - 1) * self.level / 2;
+        let lost = self.experience.saturating_sub(level_start) * percent / 100;
+        Progress { experience: self.experience - lost, ..self.clone() }
+    }
+
+    // more experience, and any levels that brings
+    pub fn gain(&self, experience: u32) -> Self {
+        let mut progress = Progress { experience: self.experience + experience, ..self.clone() };
+        while progress.experience >= progress.next_level_at() {
+            progress.level += 1;
+            progress.points += POINTS_PER_LEVEL;
+        }
+        progress
+    }
+
+    // one attribute point fewer to spend, None when there are none
+    pub fn spend_point(&self) -> Option<Self> {
+        (self.points > 0).then(|| Progress { points: self.points - 1, ..self.clone() })
+    }
+
+    pub fn skill(&self, skill: Skill) -> u32 {
+        let practice = self.practice.get(&skill).copied().unwrap_or(0);
+        (0..MAX_SKILL).take_while(|level| practice >= PRACTICE_PER_LEVEL * (level + 1) * (level + 2) / 2).count() as u32
+    }
+
+    pub fn practise(&self, skill: Skill) -> Self {
+        let mut practice = self.practice.clone();
+        *practice.entry(skill).or_insert(0) += 1;
+        Progress { practice, ..self.clone() }
+    }
+}
+
+// the character with more experience, saying so when it reaches a new level, which blesses it for a while
+pub fn gain(character: &Character, experience: u32) -> Character {
+    let progress = character.progress.gain(experience);
+    let gained = Character { progress: progress.clone(), ..character.clone() };
+    if progress.level > character.progress.level {
+        println!("{} reaches level {} and has {} attribute points to spend, see `train`", character.name, progress.level, progress.points);
+        return status::afflict(&gained, EffectKind::Blessed, status::BLESSED_TURNS);
+    }
+    gained
+}
+
+// the character after using a skill, saying so when the skill improves
+pub fn practise(character: &Character, skill: Skill) -> Character {
+    let progress = character.progress.practise(skill);
+    let level = progress.skill(skill);
+    if level > character.progress.skill(skill) {
+        println!("{}'s {} improves to {}", character.name, skill.name(), level);
+    }
+    Character { progress, ..character.clone() }
+}
+
+// the energy spent fording, less for a good swimmer
+pub fn wading_cost(character: &Character, cost: i32) -> i32 {
+    cost * (MAX_SKILL - character.progress.skill(Skill::Swimming)) as i32 / MAX_SKILL as i32
+}
+
+// the energy spent climbing, less for a good climber
+pub fn climbing_cost(character: &Character, cost: i32) -> i32 {
+    cost * (MAX_SKILL - character.progress.skill(Skill::Climbing)) as i32 / MAX_SKILL as i32
+}
+
+// the extra energy a good forager gets out of food worth value
+pub fn foraging_bonus(character: &Character, value: i32) -> i32 {
+    value * character.progress.skill(Skill::Foraging) as i32
+}
+
+// raise an attribute with a point from levelling, None when there are no points to spend
+pub fn train(character: &Character, attribute: Attribute) -> Option<Character> {
+    let progress = character.progress.spend_point()?;
+    Some(Character { progress, attributes: character.attributes.raise(attribute), ..character.clone() })
+}
+
+// the level, experience and skills, for the `me` command
+pub fn describe(character: &Character) -> String {
+    let progress = &character.progress;
+    let mut text = format!("level {} ({} of {} experience to the next), {} attribute points to spend",
+                           progress.level, progress.experience, progress.next_level_at(), progress.points);
+    for skill in Skill::ALL {
+        text.push_str(&format!("\n{} {}", skill.name(), progress.skill(skill)));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn practised(skill: Skill, times: u32) -> Progress {
+        (0..times).fold(Progress::default(), |progress, _| progress.practise(skill))
+    }
+
+    #[test]
+    fn each_level_takes_more_experience_than_the_last() {
+        let progress = Progress::default();
+        assert_eq!((progress.level(), progress.next_level_at()), (1, 100));
+        assert_eq!(progress.gain(99).level(), 1);
+        let second = progress.gain(100);
+        assert_eq!((second.level(), second.points, second.next_level_at()), (2, POINTS_PER_LEVEL, 300));
+        assert_eq!(second.gain(199).level(), 2);
+        assert_eq!(second.gain(200).next_level_at(), 600);
+    }
+
+    #[test]
+    fn a_big_gain_brings_several_levels_at_once() {
+        let progress = Progress::default().gain(650);
+        assert_eq!((progress.level(), progress.points, progress.experience), (4, 3 * POINTS_PER_LEVEL, 650));
+        assert_eq!(progress, Progress::default().gain(300).gain(300).gain(50));
+    }
+
+    #[test]
+    fn skills_improve_with_more_and_more_practice_up_to_the_most() {
+        assert_eq!(practised(Skill::Climbing, 4).skill(Skill::Climbing), 0);
+        assert_eq!(practised(Skill::Climbing, 5).skill(Skill::Climbing), 1);
+        assert_eq!(practised(Skill::Climbing, 14).skill(Skill::Climbing), 1);
+        assert_eq!(practised(Skill::Climbing, 15).skill(Skill::Climbing), 2);
+        assert_eq!(practised(Skill::Climbing, 30).skill(Skill::Climbing), 3);
+        assert_eq!(practised(Skill::Climbing, 30).skill(Skill::Swimming), 0);
+        let most = PRACTICE_PER_LEVEL * MAX_SKILL * (MAX_SKILL + 1) / 2;
+        assert_eq!(practised(Skill::Swimming, most - 1).skill(Skill::Swimming), MAX_SKILL - 1);
+        assert_eq!(practised(Skill::Swimming, most).skill(Skill::Swimming), MAX_SKILL);
+        assert_eq!(practised(Skill::Swimming, 3 * most).skill(Skill::Swimming), MAX_SKILL);
+    }
+
+    #[test]
+    fn a_setback_never_loses_a_level() {
+        let progress = Progress::default().gain(400);
+        assert_eq!(progress.setback(50).experience, 350);
+        // right at the start of a level there is nothing to lose
+        let fresh = Progress::default().gain(300);
+        assert_eq!(fresh.setback(100), fresh);
+        let emptied = progress.setback(100);
+        assert_eq!((emptied.level(), emptied.experience), (3, 300));
+        assert_eq!(emptied.setback(100).setback(100), emptied);
+        assert_eq!(Progress::default().setback(100), Progress::default());
+    }
+
+    #[test]
+    fn only_points_from_levelling_can_be_spent() {
+        assert_eq!(Progress::default().spend_point(), None);
+        let progress = Progress::default().gain(100);
+        let spent = progress.spend_point().and_then(|progress| progress.spend_point()).unwrap();
+        assert_eq!(spent.points, 0);
+        assert_eq!(spent.spend_point(), None);
+    }
+}
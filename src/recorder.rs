@@ -0,0 +1,206 @@
+// Path: src/recorder.rs
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::Path;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, ImageBuffer, ImageResult, RgbImage};
+use imageproc::drawing::{draw_filled_circle_mut, draw_filled_rect_mut, draw_line_segment_mut};
+use imageproc::rect::Rect;
+
+use crate::palette;
+use crate::{CharacterType, Color, GameEntity, GameMap, GameObject, World};
+
+// ===========================================================================
+// Recorder
+// a run of the game is recorded as where every character was, and how healthy, on every tick.
+// afterwards it is played back as an animated gif, or a numbered png for each tick,
+// with each character's trail drawn over the terrain so far.
+// only the overworld is drawn, characters underground drop out of the picture.
+
+// the frames only cover the part of the map the characters went to, with this much around it
+const MARGIN: i32 = 32;
+// frames bigger than this across are shrunk, several tiles to a pixel
+const MAX_FRAME: i32 = 512;
+const FRAME_DELAY_MS: u32 = 100;
+// gif colour reduction, 1 is best and slowest, 30 fastest
+const GIF_SPEED: i32 = 10;
+const DOT_RADIUS: i32 = 2;
+const HEALTH_BAR_WIDTH: i32 = 8;
+
+// one character at one tick
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub name: String,
+    pub character_type: CharacterType,
+    pub depth: i32,
+    pub x: i32,
+    pub y: i32,
+    pub health: i32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Recorder {
+    // the characters on each tick, in order
+    ticks: Vec<Vec<Snapshot>>,
+}
+
+impl Recorder {
+    pub fn tick_count(&self) -> usize {
+        self.ticks.len()
+    }
+
+    // note down every character as it stands now
+    pub fn capture(&mut self, world: &World) {
+        let snapshots = world.entities.iter()
+            .filter_map(|entity| match entity {
+                GameEntity::Character(character) => Some(Snapshot {
+                    name: character.name.clone(),
+                    character_type: character.character_type.clone(),
+                    depth: character.depth,
+                    x: character.x_position,
+                    y: character.y_position,
+                    health: character.health,
+                }),
+                _ => None,
+            })
+            .collect();
+        self.ticks.push(snapshots);
+    }
+
+    // save the recording, as a gif when the name ends in .gif, otherwise as numbered pngs in that directory,
+    // each frame is drawn and written before the next, so a long recording needs no more memory than a short one
+    pub fn save(&self, map: &GameMap, path: &str) -> ImageResult<()> {
+        let mut frames = Frames::new(self, map);
+        if path.ends_with(".gif") {
+            let mut encoder = GifEncoder::new_with_speed(File::create(path)?, GIF_SPEED);
+            encoder.set_repeat(Repeat::Infinite)?;
+            for tick in 0..self.ticks.len() {
+                let rgba = image::DynamicImage::ImageRgb8(frames.next_frame(tick)).to_rgba8();
+                encoder.encode_frame(image::Frame::from_parts(rgba, 0, 0, Delay::from_numer_denom_ms(FRAME_DELAY_MS, 1)))?;
+            }
+            Ok(())
+        } else {
+            fs::create_dir_all(path)?;
+            for tick in 0..self.ticks.len() {
+                frames.next_frame(tick).save(Path::new(path).join(format!("frame_{:05}.png", tick)))?;
+            }
+            Ok(())
+        }
+    }
+}
+
+// draws the ticks of a recording in order, one frame at a time
+struct Frames<'a> {
+    recorder: &'a Recorder,
+    view: View,
+    // the terrain with every trail drawn so far
+    canvas: RgbImage,
+    // the healthiest each character was, so the health bars show how much they have lost
+    most_health: HashMap<&'a str, i32>,
+}
+
+impl<'a> Frames<'a> {
+    fn new(recorder: &'a Recorder, map: &GameMap) -> Self {
+        let view = View::around(recorder, map);
+        let canvas = ImageBuffer::from_fn(view.width as u32, view.height as u32, |x, y| {
+            let (x, y) = view.to_map(x as i32, y as i32);
+            map.get_tile(x, y).color().to_rgb()
+        });
+        let mut most_health: HashMap<&str, i32> = HashMap::new();
+        for snapshot in recorder.ticks.iter().flatten() {
+            let most = most_health.entry(snapshot.name.as_str()).or_insert(1);
+            *most = (*most).max(snapshot.health);
+        }
+        Frames { recorder, view, canvas, most_health }
+    }
+
+    // the frame for tick, which must come right after the last one drawn
+    fn next_frame(&mut self, tick: usize) -> RgbImage {
+        if tick > 0 {
+            self.draw_trails(tick);
+        }
+        let mut img = self.canvas.clone();
+        for snapshot in self.recorder.ticks[tick].iter().filter(|snapshot| snapshot.depth == 0) {
+            let (x, y) = self.view.to_frame(snapshot.x, snapshot.y);
+            draw_filled_circle_mut(&mut img, (x, y), DOT_RADIUS, color(&snapshot.character_type).to_rgb());
+            draw_health_bar(&mut img, x, y - DOT_RADIUS - 3, snapshot.health as f64 / self.most_health[snapshot.name.as_str()] as f64);
+        }
+        img
+    }
+
+    // the step each character took from the tick before onto the canvas
+    fn draw_trails(&mut self, tick: usize) {
+        let (ticks, view) = (&self.recorder.ticks, &self.view);
+        for before in ticks[tick - 1].iter().filter(|snapshot| snapshot.depth == 0) {
+            let Some(after) = ticks[tick].iter().find(|snapshot| snapshot.name == before.name && snapshot.depth == 0) else { continue };
+            let from = view.to_frame(before.x, before.y);
+            let to = view.to_frame(after.x, after.y);
+            // a step over the side of a wrap-around world is not drawn across the whole map
+            if (from.0 - to.0).abs() > view.width / 2 {
+                continue;
+            }
+            draw_line_segment_mut(&mut self.canvas, (from.0 as f32, from.1 as f32), (to.0 as f32, to.1 as f32),
+                                  color(&before.character_type).darker().to_rgb());
+        }
+    }
+}
+
+fn color(character_type: &CharacterType) -> Color {
+    palette::current().character(character_type)
+}
+
+// a bar centred on x, green for the health left and red for the health lost
+fn draw_health_bar(img: &mut RgbImage, x: i32, y: i32, share: f64) {
+    let left = x - HEALTH_BAR_WIDTH / 2;
+    let healthy = (HEALTH_BAR_WIDTH as f64 * share.clamp(0.0, 1.0)).round() as i32;
+    draw_filled_rect_mut(img, Rect::at(left, y).of_size(HEALTH_BAR_WIDTH as u32, 2), Color::RED.to_rgb());
+    if healthy > 0 {
+        draw_filled_rect_mut(img, Rect::at(left, y).of_size(healthy as u32, 2), Color::LIME.to_rgb());
+    }
+}
+
+// the part of the map the frames show, and how many tiles go to a pixel
+struct View {
+    left: i32,
+    top: i32,
+    scale: i32,
+    width: i32,
+    height: i32,
+}
+
+impl View {
+    fn around(recorder: &Recorder, map: &GameMap) -> Self {
+        let config = map.config();
+        let positions: Vec<(i32, i32)> = recorder.ticks.iter().flatten()
+            .filter(|snapshot| snapshot.depth == 0)
+            .map(|snapshot| (snapshot.x, snapshot.y))
+            .collect();
+        let (left, top, right, bottom) = if positions.is_empty() {
+            (0, 0, config.width - 1, config.height - 1)
+        } else {
+            let left = positions.iter().map(|(x, _)| *x).min().unwrap_or(0) - MARGIN;
+            let top = positions.iter().map(|(_, y)| *y).min().unwrap_or(0) - MARGIN;
+            let right = positions.iter().map(|(x, _)| *x).max().unwrap_or(0) + MARGIN;
+            let bottom = positions.iter().map(|(_, y)| *y).max().unwrap_or(0) + MARGIN;
+            (left.max(0), top.max(0), right.min(config.width - 1), bottom.min(config.height - 1))
+        };
+        let across = (right - left + 1).max(bottom - top + 1);
+        let scale = (across + MAX_FRAME - 1) / MAX_FRAME;
+        View {
+            left,
+            top,
+            scale,
+            width: (right - left + 1) / scale,
+            height: (bottom - top + 1) / scale,
+        }
+    }
+
+    fn to_map(&self, x: i32, y: i32) -> (i32, i32) {
+        (self.left + x * self.scale, self.top + y * self.scale)
+    }
+
+    fn to_frame(&self, x: i32, y: i32) -> (i32, i32) {
+        ((x - self.left) / self.scale, (y - self.top) / self.scale)
+    }
+}
@@ -0,0 +1,281 @@
+// Path: src/regions.rs
+
+use std::collections::VecDeque;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rayon::prelude::*;
+
+use crate::naming::{self, NameKind};
+use crate::{GameMap, TerrainType};
+
+// ===========================================================================
+// Regions
+// the map is split into named regions so places can be described by name.
+// landmasses are the connected pieces of land, and on or around them are
+// mountain ranges, lakes and bays, each a connected patch of its own terrain.
+// a bay is sea that is mostly closed in by land.
+// regions are found on a coarse grid, one cell for every few tiles, which is
+// plenty for anything big enough to be worth a name.
+
+// tiles along each side of a grid cell
+const STEP: i32 = 4;
+// sea is a bay when this many of the eight directions reach land within BAY_REACH cells
+const BAY_SIDES: usize = 6;
+const BAY_REACH: i32 = 16;
+// smaller patches than this, in cells, are not named
+const MIN_LANDMASS: usize = 4;
+const MIN_RANGE: usize = 3;
+const MIN_LAKE: usize = 2;
+const MIN_BAY: usize = 6;
+// a landmass bigger than this share of the map is a land rather than an island
+const ISLAND_SHARE: f64 = 0.2;
+// how far around a place, in cells, is looked at for something it is near
+const NEAR_CELLS: i32 = 25;
+
+const NONE: u32 = u32::MAX;
+const DIRECTIONS: [(i32, i32); 8] = [(0, -1), (1, -1), (1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1)];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RegionKind {
+    Landmass,
+    MountainRange,
+    Lake,
+    Bay,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Region {
+    pub name: String,
+    pub kind: RegionKind,
+    // roughly the middle of the region
+    pub x: i32,
+    pub y: i32,
+    // how many tiles it covers, roughly
+    pub size: usize,
+}
+
+// what a grid cell is, for finding regions
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Cell {
+    Sea,
+    Land,
+    Mountain,
+    Lake,
+}
+
+// the regions of a map and which of them each grid cell belongs to
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Regions {
+    width: i32,
+    height: i32,
+    regions: Vec<Region>,
+    // the landmass each cell is on
+    landmass: Vec<u32>,
+    // the mountain range, lake or bay each cell is in
+    feature: Vec<u32>,
+}
+
+impl Regions {
+    pub fn all(&self) -> &[Region] {
+        &self.regions
+    }
+
+    // the landmass and the mountain range, lake or bay at x,y
+    fn at(&self, x: i32, y: i32) -> (Option<&Region>, Option<&Region>) {
+        match self.cell_index(x / STEP, y / STEP) {
+            Some(index) => (self.region(self.landmass[index]), self.region(self.feature[index])),
+            None => (None, None),
+        }
+    }
+
+    fn region(&self, id: u32) -> Option<&Region> {
+        if id == NONE { None } else { self.regions.get(id as usize) }
+    }
+
+    fn cell_index(&self, cx: i32, cy: i32) -> Option<usize> {
+        if cx < 0 || cy < 0 || cx >= self.width || cy >= self.height {
+            return None;
+        }
+        Some((cy * self.width + cx) as usize)
+    }
+
+    // the closest mountain range, lake or bay to x,y other than the one it is in
+    fn nearest_feature(&self, x: i32, y: i32, skip: Option<&Region>) -> Option<&Region> {
+        let (cx, cy) = (x / STEP, y / STEP);
+        (-NEAR_CELLS..=NEAR_CELLS)
+            .flat_map(|dy| (-NEAR_CELLS..=NEAR_CELLS).map(move |dx| (dx, dy)))
+            .filter_map(|(dx, dy)| {
+                let index = self.cell_index(cx + dx, cy + dy)?;
+                let region = self.region(self.feature[index])?;
+                Some((dx * dx + dy * dy, region))
+            })
+            .filter(|(_, region)| Some(*region) != skip)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, region)| region)
+    }
+}
+
+// find and name the regions of a map, reads every generated feature so it runs last
+pub fn generate(map: &GameMap) -> Regions {
+    let config = map.config();
+    let width = (config.width + STEP - 1) / STEP;
+    let height = (config.height + STEP - 1) / STEP;
+
+    let cells: Vec<Cell> = (0..width * height).into_par_iter()
+        .map(|index| {
+            let tile = map.generate_tile((index % width) * STEP + STEP / 2, (index / width) * STEP + STEP / 2);
+            match tile.terrain_type {
+                TerrainType::Mountain => Cell::Mountain,
+                TerrainType::Lake => Cell::Lake,
+                _ if tile.is_land() || tile.terrain_type == TerrainType::River => Cell::Land,
+                _ => Cell::Sea,
+            }
+        })
+        .collect();
+    let is_bay: Vec<bool> = (0..width * height).into_par_iter()
+        .map(|index| cells[index as usize] == Cell::Sea && is_enclosed(&cells, width, height, index % width, index / width))
+        .collect();
+
+    let mut regions = Regions {
+        width,
+        height,
+        regions: Vec::new(),
+        landmass: vec![NONE; cells.len()],
+        feature: vec![NONE; cells.len()],
+    };
+    let mut rng = StdRng::seed_from_u64((config.seed as u64) << 8 | 0xA0);
+    let map_cells = cells.len() as f64;
+
+    for index in 0..cells.len() {
+        let cell = cells[index];
+        if cell != Cell::Sea && regions.landmass[index] == NONE {
+            let patch = flood(width, height, index, |i| cells[i] != Cell::Sea && regions.landmass[i] == NONE);
+            let kind = if patch.len() as f64 > map_cells * ISLAND_SHARE { NameKind::Land } else { NameKind::Island };
+            add_region(&mut regions, &mut rng, patch, MIN_LANDMASS, RegionKind::Landmass, kind, true);
+        }
+        let feature = match cell {
+            Cell::Mountain => Some((MIN_RANGE, RegionKind::MountainRange, NameKind::MountainRange)),
+            Cell::Lake => Some((MIN_LAKE, RegionKind::Lake, NameKind::Lake)),
+            Cell::Sea if is_bay[index] => Some((MIN_BAY, RegionKind::Bay, NameKind::Bay)),
+            _ => None,
+        };
+        if let Some((min_size, region_kind, name_kind)) = feature {
+            if regions.feature[index] == NONE {
+                let patch = flood(width, height, index, |i| {
+                    cells[i] == cell && (cell != Cell::Sea || is_bay[i]) && regions.feature[i] == NONE
+                });
+                add_region(&mut regions, &mut rng, patch, min_size, region_kind, name_kind, false);
+            }
+        }
+    }
+    regions
+}
+
+// name a patch of cells and mark them as belonging to it, or leave them unnamed if it is too small
+fn add_region(regions: &mut Regions, rng: &mut StdRng, patch: Vec<usize>, min_size: usize,
+              kind: RegionKind, name_kind: NameKind, is_landmass: bool) {
+    // too small ones are still marked, so they are not flooded again
+    let id = if patch.len() >= min_size { regions.regions.len() as u32 } else { NONE - 1 };
+    let layer = if is_landmass { &mut regions.landmass } else { &mut regions.feature };
+    for &index in &patch {
+        layer[index] = id;
+    }
+    if id == NONE - 1 {
+        return;
+    }
+    let width = regions.width as usize;
+    let x = patch.iter().map(|index| index % width).sum::<usize>() / patch.len();
+    let y = patch.iter().map(|index| index / width).sum::<usize>() / patch.len();
+    regions.regions.push(Region {
+        name: naming::name(rng, name_kind),
+        kind,
+        x: x as i32 * STEP + STEP / 2,
+        y: y as i32 * STEP + STEP / 2,
+        size: patch.len() * (STEP * STEP) as usize,
+    });
+}
+
+// every cell joined to start by cells that belong, start included
+fn flood<F>(width: i32, height: i32, start: usize, belongs: F) -> Vec<usize>
+    where F: Fn(usize) -> bool
+{
+    let mut patch = vec![start];
+    let mut seen = std::collections::HashSet::from([start]);
+    let mut queue = VecDeque::from([start]);
+    while let Some(index) = queue.pop_front() {
+        let (x, y) = (index as i32 % width, index as i32 / width);
+        for (dx, dy) in [(0, -1), (1, 0), (0, 1), (-1, 0)] {
+            let (nx, ny) = (x + dx, y + dy);
+            if nx < 0 || ny < 0 || nx >= width || ny >= height {
+                continue;
+            }
+            let next = (ny * width + nx) as usize;
+            if belongs(next) && seen.insert(next) {
+                patch.push(next);
+                queue.push_back(next);
+            }
+        }
+    }
+    patch
+}
+
+// is the sea at cx,cy closed in by land on most sides
+fn is_enclosed(cells: &[Cell], width: i32, height: i32, cx: i32, cy: i32) -> bool {
+    let sides = DIRECTIONS.iter()
+        .filter(|(dx, dy)| {
+            (1..=BAY_REACH).any(|distance| {
+                let (x, y) = (cx + dx * distance, cy + dy * distance);
+                x >= 0 && y >= 0 && x < width && y < height && cells[(y * width + x) as usize] != Cell::Sea
+            })
+        })
+        .count();
+    sides >= BAY_SIDES
+}
+
+// where x,y is, in words, such as "on the slopes of the Gravenspine, near Lake Oss"
+pub fn describe(map: &GameMap, x: i32, y: i32) -> String {
+    if map.depth() > 0 {
+        return format!("underground, {} levels down", map.depth());
+    }
+    let (landmass, feature) = map.regions.at(x, y);
+    let place = match (map.settlement_at(x, y), feature, landmass) {
+        (Some(settlement), _, _) => format!("in {}", settlement.name),
+        (None, Some(region), _) => match region.kind {
+            RegionKind::MountainRange => format!("on the slopes of {}", region.name),
+            RegionKind::Bay => format!("in {}", region.name),
+            _ => format!("on {}", region.name),
+        },
+        (None, None, Some(region)) => format!("on {}", region.name),
+        (None, None, None) => "at sea".to_string(),
+    };
+    match map.regions.nearest_feature(x, y, feature) {
+        Some(near) => format!("{}, near {}", place, near.name),
+        None => place,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world_gen::WorldGenConfig;
+    use crate::GameObject;
+
+    #[test]
+    fn the_default_mountains_are_within_reach_of_the_land() {
+        let config = WorldGenConfig::default();
+        assert!(0 < config.mountain_threshold && config.mountain_threshold as f64 <= config.elevation_scale - config.sea_level);
+    }
+
+    #[test]
+    fn high_ground_makes_a_named_mountain_range() {
+        let map = GameMap::new(WorldGenConfig { width: 256, height: 256, falloff_margin: 80.0, ..WorldGenConfig::with_seed(4) });
+        let ranges: Vec<&Region> = map.regions().all().iter().filter(|region| region.kind == RegionKind::MountainRange).collect();
+        assert!(!ranges.is_empty());
+
+        let (x, y) = (0..256).flat_map(|y| (0..256).map(move |x| (x, y)))
+            .find(|&(x, y)| map.regions.at(x, y).1.is_some_and(|region| region.kind == RegionKind::MountainRange) && map.settlement_at(x, y).is_none())
+            .expect("a tile in a mountain range");
+        assert!(describe(&map, x, y).starts_with("on the slopes of the "), "{}", describe(&map, x, y));
+        assert!(map.get_tile(x / STEP * STEP + STEP / 2, y / STEP * STEP + STEP / 2).terrain_type == TerrainType::Mountain);
+    }
+}
@@ -0,0 +1,162 @@
+// Path: src/relief.rs
+
+use image::RgbImage;
+use rayon::prelude::*;
+
+use crate::erosion::ElevationGrid;
+use crate::palette;
+use crate::{Color, GameMap, GameObject, TerrainType};
+
+// ===========================================================================
+// Relief
+// ways of drawing the terrain so the lie of the land can be read off the map.
+// hillshading darkens the slopes facing away from the sun,
+// contour lines join up ground of the same height,
+// and a hypsometric tint colours the ground by its height instead of its biome,
+// in the height and depth colours of the theme.
+
+// the sun is this high above the horizon, in degrees
+const SUN_ALTITUDE: f64 = 45.0;
+// the height field is gentle, so slopes are made steeper to show up in the shading
+const HILLSHADE_EXAGGERATION: f64 = 40.0;
+// how dark the darkest slope and the contour lines get, 0-1
+const SHADOW: f64 = 0.45;
+const CONTOUR_DARKNESS: f64 = 0.5;
+// ground lower than this is under water, see Tile::from_elevation
+const SHORE: f64 = 1.0;
+
+
+// how the terrain is coloured
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tint {
+    // each terrain in its own colour
+    Terrain,
+    // the ground and sea coloured by height
+    Hypsometric,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MapStyle {
+    pub tint: Tint,
+    // where the sun is, in degrees clockwise from north, no hillshading when None
+    pub sun_azimuth: Option<f64>,
+    // the height between contour lines, no contours when None
+    pub contour_interval: Option<f64>,
+}
+
+impl Default for MapStyle {
+    fn default() -> Self {
+        MapStyle { tint: Tint::Terrain, sun_azimuth: None, contour_interval: None }
+    }
+}
+
+impl MapStyle {
+    // read one style option, such as "hillshade=315", "contours=5" or "hypsometric"
+    // returns false if the option is not a style option
+    pub fn set_option(&mut self, option: &str) -> bool {
+        let (name, value) = match option.split_once('=') {
+            Some((name, value)) => (name, value.parse::<f64>().ok()),
+            None => (option, None),
+        };
+        match name {
+            "hillshade" => self.sun_azimuth = Some(value.unwrap_or(315.0)),
+            "contours" => self.contour_interval = Some(value.unwrap_or(5.0).max(0.5)),
+            "hypsometric" => self.tint = Tint::Hypsometric,
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn is_plain(&self) -> bool {
+        *self == MapStyle::default()
+    }
+}
+
+// redraw the terrain of a whole map image in the given style
+pub fn apply(img: &mut RgbImage, map: &GameMap, style: &MapStyle) {
+    if style.is_plain() {
+        return;
+    }
+    let config = map.config();
+    let heights = ElevationGrid::from_fn(config.width, config.height, |x, y| map.elevation_at(x, y));
+    // the highest the ground can get, and the deepest the sea
+    let peak = (config.elevation_scale - config.sea_level).max(1.0);
+    let deep = config.sea_level.max(1.0);
+    let width = img.width() as usize;
+    let theme = palette::current();
+
+    img.par_chunks_mut(width * 3).enumerate().for_each(|(y, row)| {
+        let y = y as i32;
+        for (x, pixel) in row.chunks_mut(3).enumerate() {
+            let x = x as i32;
+            let height = heights.get(x, y);
+            let mut color = Color { r: pixel[0], g: pixel[1], b: pixel[2] };
+
+            if style.tint == Tint::Hypsometric {
+                match map.get_tile(x, y).terrain_type {
+                    TerrainType::Water => color = theme.depth_tint(1.0 + height / deep),
+                    terrain if is_open_ground(terrain) => color = theme.height_tint(height / peak),
+                    _ => (),
+                }
+            }
+            if let Some(azimuth) = style.sun_azimuth {
+                if height >= SHORE {
+                    color = scale(color, 1.0 - SHADOW + SHADOW * hillshade(&heights, x, y, azimuth));
+                }
+            }
+            if let Some(interval) = style.contour_interval {
+                if height >= SHORE && is_contour(&heights, x, y, interval) {
+                    color = scale(color, CONTOUR_DARKNESS);
+                }
+            }
+            pixel.copy_from_slice(&[color.r, color.g, color.b]);
+        }
+    });
+}
+
+// ground that takes the height tint, towns, roads and fresh water keep their own colours
+fn is_open_ground(terrain: TerrainType) -> bool {
+    matches!(terrain,
+        TerrainType::Earth | TerrainType::Grass | TerrainType::Beach | TerrainType::Mountain | TerrainType::Forest |
+        TerrainType::Desert | TerrainType::Tundra | TerrainType::Swamp | TerrainType::Jungle | TerrainType::Snow)
+}
+
+// how brightly the sun lights x,y, 0-1
+fn hillshade(heights: &ElevationGrid, x: i32, y: i32, azimuth: f64) -> f64 {
+    let dx = (heights.get(x + 1, y) - heights.get(x - 1, y)) / 2.0 * HILLSHADE_EXAGGERATION;
+    let dy = (heights.get(x, y + 1) - heights.get(x, y - 1)) / 2.0 * HILLSHADE_EXAGGERATION;
+    // the way the ground faces, against the way to the sun
+    // the map has y going down, so north is up and the azimuth turns clockwise
+    let normal = (-dx, -dy, 1.0);
+    let (azimuth, altitude) = (azimuth.to_radians(), SUN_ALTITUDE.to_radians());
+    let sun = (azimuth.sin() * altitude.cos(), -azimuth.cos() * altitude.cos(), altitude.sin());
+    let length = (normal.0 * normal.0 + normal.1 * normal.1 + 1.0).sqrt();
+    let light = (normal.0 * sun.0 + normal.1 * sun.1 + normal.2 * sun.2) / length;
+    light.clamp(0.0, 1.0)
+}
+
+// does a contour line pass between x,y and its neighbour to the east or south
+fn is_contour(heights: &ElevationGrid, x: i32, y: i32, interval: f64) -> bool {
+    let band = |x, y| (heights.get(x, y) / interval).floor();
+    let here = band(x, y);
+    here != band(x + 1, y) || here != band(x, y + 1)
+}
+
+// the colour a share of the way along a ramp of colours
+pub fn ramp(stops: &[(f64, Color)], share: f64) -> Color {
+    let share = share.clamp(0.0, 1.0);
+    for pair in stops.windows(2) {
+        let ((from, low), (to, high)) = (pair[0], pair[1]);
+        if share <= to {
+            let t = ((share - from) / (to - from)).clamp(0.0, 1.0);
+            let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * t).round() as u8;
+            return Color { r: mix(low.r, high.r), g: mix(low.g, high.g), b: mix(low.b, high.b) };
+        }
+    }
+    stops[stops.len() - 1].1
+}
+
+fn scale(color: Color, factor: f64) -> Color {
+    let channel = |value: u8| (value as f64 * factor).clamp(0.0, 255.0) as u8;
+    Color { r: channel(color.r), g: channel(color.g), b: channel(color.b) }
+}
@@ -0,0 +1,390 @@
+// Path: src/settlement.rs
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt;
+use noise::NoiseFn;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::hydrology;
+use crate::naming::{self, NameKind};
+use crate::{GameMap, TerrainType, Tile};
+
+// ===========================================================================
+// Settlements and roads
+// towns are built on grassland that is flat, near the coast and near fresh water.
+// the best spots get cities, the rest towns and villages.
+// the settlements are joined up by roads that follow the easiest ground,
+// with a bridge wherever a road has to cross a river or lake.
+
+// how many random spots are looked at when choosing where to build
+const SETTLEMENT_SAMPLES: usize = 3000;
+// settlements closer than this would run into each other
+const SETTLEMENT_SPACING: i32 = 120;
+// how far around a spot is checked for flat ground, the coast and fresh water
+const FLAT_RADIUS: i32 = 3;
+const COAST_REACH: i32 = 40;
+const WATER_REACH: i32 = 24;
+// how much each thing counts towards a good spot
+const COAST_WEIGHT: f64 = 2.0;
+const WATER_WEIGHT: f64 = 2.0;
+const FLATNESS_WEIGHT: f64 = 1.0;
+// a step of road costs 1, more going up or down hill, and a lot more to bridge water
+const SLOPE_COST: f64 = 4.0;
+const BRIDGE_COST: f64 = 10.0;
+// a little extra cost that varies over the land, so roads bend instead of running dead straight
+const ROAD_ROUGHNESS: f64 = 0.6;
+const ROUGHNESS_SCALE: f64 = 25.0;
+// a road that has not found its way after looking at this many tiles is not built
+const MAX_ROAD_SEARCH: usize = 1_000_000;
+
+const DIRECTIONS: [(i32, i32); 8] = [(0, -1), (1, -1), (1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1)];
+const NEIGHBORS: [(i32, i32); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SettlementSize {
+    Village,
+    Town,
+    City,
+}
+
+impl SettlementSize {
+    // how far the houses spread from the centre
+    pub fn radius(&self) -> i32 {
+        match self {
+            SettlementSize::Village => 2,
+            SettlementSize::Town => 4,
+            SettlementSize::City => 7,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            SettlementSize::Village => "village",
+            SettlementSize::Town => "town",
+            SettlementSize::City => "city",
+        }
+    }
+}
+
+// a named place on the map, shops and people can be attached to it by name
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settlement {
+    pub name: String,
+    pub size: SettlementSize,
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Settlement {
+    // is x,y within the settlement
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        let (dx, dy) = (x - self.x, y - self.y);
+        let radius = self.size.radius();
+        dx * dx + dy * dy <= radius * radius
+    }
+}
+
+impl fmt::Display for Settlement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, a {} at ({}, {})", self.name, self.size.name(), self.x, self.y)
+    }
+}
+
+// a tile waiting in the road search, the cheapest estimate comes out of the heap first
+struct RoadStep {
+    estimate: f64,
+    x: i32,
+    y: i32,
+}
+
+impl PartialEq for RoadStep {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for RoadStep {}
+
+impl PartialOrd for RoadStep {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for RoadStep {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate)
+            .then_with(|| (other.y, other.x).cmp(&(self.y, self.x)))
+    }
+}
+
+// choose the settlements for a map and work out their city, road and bridge tiles.
+// reads the rivers and lakes, so it runs after the hydrology pass
+pub fn generate(map: &GameMap) -> (Vec<Settlement>, HashMap<(i32, i32), Tile>) {
+    let settlements = place_settlements(map);
+    let mut tiles = HashMap::new();
+
+    // join every settlement to the nearest one already on the network,
+    // so the roads form a tree that reaches all of them
+    let mut connected = vec![false; settlements.len()];
+    if let Some(first) = connected.first_mut() {
+        *first = true;
+    }
+    for _ in 1..settlements.len() {
+        let closest = (0..settlements.len())
+            .filter(|&from| connected[from])
+            .flat_map(|from| (0..settlements.len()).filter(|&to| !connected[to]).map(move |to| (from, to)))
+            .min_by_key(|&(from, to)| {
+                let (dx, dy) = (settlements[from].x - settlements[to].x, settlements[from].y - settlements[to].y);
+                dx * dx + dy * dy
+            });
+        let Some((from, to)) = closest else { break };
+        connected[to] = true;
+        let path = find_road(map, (settlements[from].x, settlements[from].y), (settlements[to].x, settlements[to].y));
+        for (x, y) in path.unwrap_or_default() {
+            if settlements.iter().any(|settlement| settlement.contains(x, y)) {
+                continue;
+            }
+            let tile = map.generate_tile(x, y);
+            let terrain_type = if is_fresh_water(map, x, y) { TerrainType::Bridge } else { TerrainType::Road };
+            tiles.insert((x, y), Tile { terrain_type, depth: 0, ..tile });
+        }
+    }
+
+    for settlement in &settlements {
+        let radius = settlement.size.radius();
+        for y in settlement.y - radius..=settlement.y + radius {
+            for x in settlement.x - radius..=settlement.x + radius {
+                let tile = map.generate_tile(x, y);
+                if settlement.contains(x, y) && tile.is_land() {
+                    tiles.insert((x, y), Tile { terrain_type: TerrainType::City, ..tile });
+                }
+            }
+        }
+    }
+    (settlements, tiles)
+}
+
+// the best spots for settlements, best first, kept apart from each other
+fn place_settlements(map: &GameMap) -> Vec<Settlement> {
+    let config = map.config();
+    let mut rng = StdRng::seed_from_u64((config.seed as u64) << 8 | 0x80);
+
+    let mut candidates: Vec<(f64, i32, i32)> = (0..SETTLEMENT_SAMPLES)
+        .filter_map(|_| {
+            let x = rng.gen_range(0..config.width);
+            let y = rng.gen_range(0..config.height);
+            score(map, x, y).map(|score| (score, x, y))
+        })
+        .collect();
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0).then((a.1, a.2).cmp(&(b.1, b.2))));
+
+    let mut spots: Vec<(i32, i32)> = Vec::new();
+    for (_, x, y) in candidates {
+        if spots.len() >= config.settlement_count {
+            break;
+        }
+        let crowded = spots.iter().any(|(sx, sy)| (sx - x).abs() < SETTLEMENT_SPACING && (sy - y).abs() < SETTLEMENT_SPACING);
+        if !crowded {
+            spots.push((x, y));
+        }
+    }
+
+    // the best quarter become cities, the next quarter towns, the rest villages
+    let cities = spots.len().div_ceil(4);
+    let towns = spots.len().div_ceil(2);
+    spots.into_iter().enumerate()
+        .map(|(rank, (x, y))| {
+            let size = if rank < cities {
+                SettlementSize::City
+            } else if rank < towns {
+                SettlementSize::Town
+            } else {
+                SettlementSize::Village
+            };
+            Settlement { name: naming::name(&mut rng, NameKind::Town), size, x, y }
+        })
+        .collect()
+}
+
+// how good a place x,y is to build on, None if it can not be built on at all
+fn score(map: &GameMap, x: i32, y: i32) -> Option<f64> {
+    if map.generate_tile(x, y).terrain_type != TerrainType::Grass {
+        return None;
+    }
+
+    // the difference between the highest and lowest ground nearby
+    let heights: Vec<f64> = (-FLAT_RADIUS..=FLAT_RADIUS)
+        .flat_map(|dy| (-FLAT_RADIUS..=FLAT_RADIUS).map(move |dx| (x + dx, y + dy)))
+        .map(|(nx, ny)| map.elevation_at(nx, ny))
+        .collect();
+    let highest = heights.iter().cloned().fold(f64::MIN, f64::max);
+    let lowest = heights.iter().cloned().fold(f64::MAX, f64::min);
+
+    let coast = distance_to(x, y, COAST_REACH, |nx, ny| hydrology::is_sea(map, nx, ny));
+    let water = distance_to(x, y, WATER_REACH, |nx, ny| is_fresh_water(map, nx, ny));
+    let nearness = |distance: Option<i32>, reach: i32| distance.map_or(0.0, |d| 1.0 - d as f64 / reach as f64);
+    Some(COAST_WEIGHT * nearness(coast, COAST_REACH)
+        + WATER_WEIGHT * nearness(water, WATER_REACH)
+        - FLATNESS_WEIGHT * (highest - lowest))
+}
+
+// look out from x,y in the eight directions for the nearest tile that is wanted
+fn distance_to<F>(x: i32, y: i32, reach: i32, wanted: F) -> Option<i32>
+    where F: Fn(i32, i32) -> bool
+{
+    (1..=reach).find(|&distance| {
+        DIRECTIONS.iter().any(|(dx, dy)| wanted(x + dx * distance, y + dy * distance))
+    })
+}
+
+fn roughness(map: &GameMap, x: i32, y: i32) -> f64 {
+    let noise = map.noise.meander.get([x as f64 / ROUGHNESS_SCALE, y as f64 / ROUGHNESS_SCALE]);
+    ROAD_ROUGHNESS * (noise + 1.0) / 2.0
+}
+
+fn is_fresh_water(map: &GameMap, x: i32, y: i32) -> bool {
+    map.features.get(&(x, y))
+        .is_some_and(|tile| tile.terrain_type == TerrainType::River || tile.terrain_type == TerrainType::Lake)
+}
+
+// the cheapest way over the land from one settlement to another, None if the sea is in the way
+fn find_road(map: &GameMap, from: (i32, i32), to: (i32, i32)) -> Option<Vec<(i32, i32)>> {
+    cheapest_path(from, to, |(x, y), (nx, ny)| {
+        if hydrology::is_sea(map, nx, ny) {
+            return None;
+        }
+        Some(if is_fresh_water(map, nx, ny) {
+            BRIDGE_COST
+        } else {
+            1.0 + SLOPE_COST * (map.elevation_at(nx, ny) - map.elevation_at(x, y)).abs() + roughness(map, nx, ny)
+        })
+    })
+}
+
+// the cheapest path from one tile to another over the four neighbours (A*),
+// step_cost is what a step from one tile to the next costs, None where it can not be taken.
+// every step costs at least 1, so the distance left never overestimates and the path found is the cheapest
+fn cheapest_path<F>(from: (i32, i32), to: (i32, i32), step_cost: F) -> Option<Vec<(i32, i32)>>
+    where F: Fn((i32, i32), (i32, i32)) -> Option<f64>
+{
+    let mut heap = BinaryHeap::new();
+    let mut cost: HashMap<(i32, i32), f64> = HashMap::new();
+    let mut came_from: HashMap<(i32, i32), (i32, i32)> = HashMap::new();
+    let remaining = |x: i32, y: i32| ((x - to.0).abs() + (y - to.1).abs()) as f64;
+    heap.push(RoadStep { estimate: remaining(from.0, from.1), x: from.0, y: from.1 });
+    cost.insert(from, 0.0);
+
+    let mut searched = 0;
+    while let Some(step) = heap.pop() {
+        if (step.x, step.y) == to {
+            let mut path = vec![to];
+            let mut position = to;
+            while let Some(&previous) = came_from.get(&position) {
+                path.push(previous);
+                position = previous;
+            }
+            path.reverse();
+            return Some(path);
+        }
+        searched += 1;
+        if searched > MAX_ROAD_SEARCH {
+            return None;
+        }
+
+        let here = cost[&(step.x, step.y)];
+        // an old entry for a tile that has since been reached more cheaply
+        if step.estimate > here + remaining(step.x, step.y) {
+            continue;
+        }
+        for (dx, dy) in NEIGHBORS {
+            let (nx, ny) = (step.x + dx, step.y + dy);
+            let Some(step_cost) = step_cost((step.x, step.y), (nx, ny)) else { continue };
+            let next = here + step_cost;
+            if cost.get(&(nx, ny)).is_none_or(|&old| next < old) {
+                cost.insert((nx, ny), next);
+                came_from.insert((nx, ny), (step.x, step.y));
+                heap.push(RoadStep { estimate: next + remaining(nx, ny), x: nx, y: ny });
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: i32 = 24;
+
+    // a lake across the middle of a small square of land, with the land outside the square impassable
+    fn lake_step_cost((x, y): (i32, i32), (nx, ny): (i32, i32)) -> Option<f64> {
+        if !(0..SIZE).contains(&nx) || !(0..SIZE).contains(&ny) {
+            return None;
+        }
+        if is_lake(nx, ny) {
+            return Some(BRIDGE_COST);
+        }
+        // uneven ground so there is only one cheapest way round
+        Some(1.0 + ((nx * 7 + ny * 3 + x + y) % 5) as f64 * 0.3)
+    }
+
+    fn is_lake(x: i32, y: i32) -> bool {
+        (9..=14).contains(&x) && (3..=19).contains(&y)
+    }
+
+    fn path_cost(path: &[(i32, i32)]) -> f64 {
+        path.windows(2).map(|step| lake_step_cost(step[0], step[1]).unwrap()).sum()
+    }
+
+    // the cheapest cost from one tile to every other, by relaxing every step until nothing changes
+    fn cheapest_costs(from: (i32, i32)) -> HashMap<(i32, i32), f64> {
+        let mut costs = HashMap::from([(from, 0.0)]);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for y in 0..SIZE {
+                for x in 0..SIZE {
+                    let Some(&here) = costs.get(&(x, y)) else { continue };
+                    for (dx, dy) in NEIGHBORS {
+                        let Some(step) = lake_step_cost((x, y), (x + dx, y + dy)) else { continue };
+                        let next = here + step;
+                        if costs.get(&(x + dx, y + dy)).is_none_or(|&old| next < old - 1e-9) {
+                            costs.insert((x + dx, y + dy), next);
+                            changed = true;
+                        }
+                    }
+                }
+            }
+        }
+        costs
+    }
+
+    #[test]
+    fn road_goes_round_a_lake_the_cheapest_way() {
+        let (from, to) = ((2, 11), (21, 11));
+        let path = cheapest_path(from, to, lake_step_cost).expect("a road round the lake");
+        assert_eq!((path.first(), path.last()), (Some(&from), Some(&to)));
+        assert!(path.windows(2).all(|step| (step[0].0 - step[1].0).abs() + (step[0].1 - step[1].1).abs() == 1));
+        assert!(path.iter().all(|&(x, y)| !is_lake(x, y)));
+        assert!((path_cost(&path) - cheapest_costs(from)[&to]).abs() < 1e-9);
+    }
+
+    #[test]
+    fn every_road_is_the_cheapest() {
+        let from = (0, 0);
+        let costs = cheapest_costs(from);
+        for to in [(23, 23), (23, 0), (11, 11), (12, 20), (0, 23)] {
+            let path = cheapest_path(from, to, lake_step_cost).unwrap();
+            assert!((path_cost(&path) - costs[&to]).abs() < 1e-9, "road to {:?}", to);
+        }
+    }
+
+    #[test]
+    fn no_road_when_the_way_is_blocked() {
+        let walled = |here: (i32, i32), next: (i32, i32)| if next.0 == 5 { None } else { lake_step_cost(here, next) };
+        assert_eq!(cheapest_path((2, 2), (20, 2), walled), None);
+    }
+}
@@ -0,0 +1,104 @@
+// Path: src/shape.rs
+
+use std::f64::consts::TAU;
+use noise::{NoiseFn, Perlin};
+
+use crate::world_gen::{WorldGenConfig, WorldShape};
+
+// ===========================================================================
+// World shapes
+// the noise is scaled by a falloff that is high where there should be land
+// and drops below sea level where there should be sea.
+// an island falls away from the centre of the map, an archipelago from several
+// centres, a continent from a big ragged blob, and a wrap-around world has no
+// falloff at all, its noise is sampled around a cylinder so the east and west
+// edges meet without a seam.
+
+// an island's falloff drops by this much over its radius, the same slope as the single island
+const FALLOFF_SLOPE: f64 = 0.33;
+// islands of an archipelago keep this fraction of the map clear at the edges
+const ARCHIPELAGO_MARGIN: f64 = 0.15;
+// island radius as a fraction of the map, smallest and the most added on top
+const ISLAND_RADIUS: f64 = 0.05;
+const ISLAND_RADIUS_SPREAD: f64 = 0.06;
+// a continent reaches this fraction of the map from the centre
+const CONTINENT_RADIUS: f64 = 0.42;
+// how far the coast of a continent or archipelago wanders in and out, and how fine its bays are
+const RAGGEDNESS: f64 = 0.45;
+const COAST_FREQUENCY: f64 = 6.0;
+// a wrap-around world is this high everywhere, about half of it ends up land
+const WRAP_FALLOFF: f64 = 0.7;
+// with no falloff to shape the land, a wrap-around world needs smaller features
+const WRAP_FREQUENCY: f64 = 3.0;
+
+// how much of the noise becomes height at x,y, 1 at the heart of the land
+pub fn falloff(config: &WorldGenConfig, perlin: &Perlin, x: f64, y: f64) -> f64 {
+    let width = config.width as f64;
+    let height = config.height as f64;
+    match config.shape {
+        WorldShape::Island => {
+            let distance = distance(x, y, width / 2.0, height / 2.0);
+            let max_distance = (width * width + height * height).sqrt() - config.falloff_margin;
+            1.0 - distance / max_distance
+        }
+        WorldShape::Archipelago => {
+            let size = width.min(height);
+            let ragged = 1.0 + RAGGEDNESS * coast(config, perlin, x, y);
+            (0..config.island_count as u64)
+                .map(|island| {
+                    let centre_x = width * (ARCHIPELAGO_MARGIN + (1.0 - 2.0 * ARCHIPELAGO_MARGIN) * scatter(config.seed, island * 3));
+                    let centre_y = height * (ARCHIPELAGO_MARGIN + (1.0 - 2.0 * ARCHIPELAGO_MARGIN) * scatter(config.seed, island * 3 + 1));
+                    let radius = size * (ISLAND_RADIUS + ISLAND_RADIUS_SPREAD * scatter(config.seed, island * 3 + 2));
+                    1.0 - FALLOFF_SLOPE * distance(x, y, centre_x, centre_y) * ragged / radius
+                })
+                .fold(-1.0, f64::max)
+        }
+        WorldShape::Continent => {
+            let radius = width.min(height) * CONTINENT_RADIUS;
+            let distance = distance(x, y, width / 2.0, height / 2.0) * (1.0 + RAGGEDNESS * coast(config, perlin, x, y));
+            1.0 - FALLOFF_SLOPE * distance / radius
+        }
+        WorldShape::Wrap => WRAP_FALLOFF,
+    }
+}
+
+// the noise the height of x,y is made from
+pub fn elevation_noise(config: &WorldGenConfig, perlin: &Perlin, x: f64, y: f64) -> f64 {
+    let frequency = if config.shape == WorldShape::Wrap { WRAP_FREQUENCY } else { 1.0 };
+    sample(config, perlin, x, y, frequency)
+}
+
+// noise at x,y with the given number of features across the map.
+// a wrap-around world reads its noise around a cylinder, so x wraps without a seam
+pub fn sample(config: &WorldGenConfig, perlin: &Perlin, x: f64, y: f64, frequency: f64) -> f64 {
+    let ny = y / config.height as f64 * frequency;
+    if config.shape == WorldShape::Wrap {
+        let angle = x / config.width as f64 * TAU;
+        let radius = frequency / TAU;
+        perlin.get([radius * angle.cos(), radius * angle.sin(), ny])
+    } else {
+        perlin.get([x / config.width as f64 * frequency, ny])
+    }
+}
+
+// two octaves of noise that push a coast in and out, read well away from the elevation noise
+fn coast(config: &WorldGenConfig, perlin: &Perlin, x: f64, y: f64) -> f64 {
+    let nx = x / config.width as f64 * COAST_FREQUENCY + 100.5;
+    let ny = y / config.height as f64 * COAST_FREQUENCY + 100.5;
+    perlin.get([nx, ny]) + 0.5 * perlin.get([nx * 3.0, ny * 3.0])
+}
+
+fn distance(x: f64, y: f64, to_x: f64, to_y: f64) -> f64 {
+    let (dx, dy) = (x - to_x, y - to_y);
+    (dx * dx + dy * dy).sqrt()
+}
+
+// a number from 0 to 1 picked by the seed and an index, the same every time
+fn scatter(seed: u32, index: u64) -> f64 {
+    // splitmix64
+    let mut z = ((seed as u64) << 32 | index).wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
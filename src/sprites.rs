@@ -0,0 +1,183 @@
+// Path: src/sprites.rs
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use image::{imageops, ImageBuffer, Rgba, RgbaImage};
+
+use crate::palette;
+use crate::{Character, Color, Direction, GameEntity, GameObject, TerrainType, World};
+
+// ===========================================================================
+// Sprites
+// a screenshot of the game around the player, each tile drawn as a picture from a sprite atlas.
+// the atlas is a png cut into square cells, with a mapping file saying which cell each
+// terrain, item and character is drawn with, see assets/tiles.txt.
+// the picture is built up in layers: the terrain at the back, then the edges where one
+// terrain meets another, then items and characters at the front.
+
+pub const DEFAULT_MAPPING: &str = "assets/tiles.txt";
+pub const DEFAULT_WIDTH: i32 = 32;
+pub const DEFAULT_HEIGHT: i32 = 20;
+
+// the sides of a tile that edges are drawn on
+const SIDES: [Direction; 4] = [Direction::North, Direction::East, Direction::South, Direction::West];
+
+// the sprites cut out of an atlas, by the name of what they are drawn for
+pub struct Atlas {
+    cell: u32,
+    terrain: HashMap<String, RgbaImage>,
+    // by the terrain drawn over, the terrain next to it and the side it is on
+    edges: HashMap<(String, String, Direction), RgbaImage>,
+    items: HashMap<String, RgbaImage>,
+    characters: HashMap<String, RgbaImage>,
+}
+
+impl Atlas {
+    // read a mapping file and the atlas it names, which is found next to it
+    pub fn load(mapping: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(mapping).map_err(|e| format!("Failed to read {}: {}", mapping, e))?;
+        let mut atlas = None;
+        let mut sprites = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words[..] {
+                ["atlas", file, cell] => {
+                    let path = Path::new(mapping).parent().unwrap_or(Path::new("")).join(file);
+                    let image = image::open(&path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+                    let cell = cell.parse::<u32>().ok().filter(|cell| *cell > 0)
+                        .ok_or_else(|| format!("{} line {}: bad cell size {}", mapping, number + 1, cell))?;
+                    atlas = Some((image.to_rgba8(), cell));
+                }
+                _ => sprites.push((number + 1, words)),
+            }
+        }
+        let Some((image, cell)) = atlas else { return Err(format!("{} does not name an atlas", mapping)) };
+
+        let mut atlas = Atlas { cell, terrain: HashMap::new(), edges: HashMap::new(), items: HashMap::new(), characters: HashMap::new() };
+        for (number, words) in sprites {
+            let bad = || format!("{} line {}: {}", mapping, number, words.join(" "));
+            let (column, row) = match words[words.len().saturating_sub(2)..] {
+                [column, row] => (column.parse::<u32>().map_err(|_| bad())?, row.parse::<u32>().map_err(|_| bad())?),
+                _ => return Err(bad()),
+            };
+            if (column + 1) * cell > image.width() || (row + 1) * cell > image.height() {
+                return Err(format!("{} line {}: cell {},{} is outside the atlas", mapping, number, column, row));
+            }
+            let sprite = imageops::crop_imm(&image, column * cell, row * cell, cell, cell).to_image();
+            match words[..] {
+                ["terrain", name, _, _] => { atlas.terrain.insert(name.to_string(), sprite); }
+                ["item", name, _, _] => { atlas.items.insert(name.to_string(), sprite); }
+                ["character", name, _, _] => { atlas.characters.insert(name.to_string(), sprite); }
+                ["edge", name, next, side, _, _] => {
+                    let side = side.parse::<Direction>().ok().filter(|side| SIDES.contains(side)).ok_or_else(bad)?;
+                    atlas.edges.insert((name.to_string(), next.to_string(), side), sprite);
+                }
+                _ => return Err(bad()),
+            }
+        }
+        Ok(atlas)
+    }
+
+    // how many sprites are in the atlas, to say what was loaded
+    pub fn sprite_count(&self) -> usize {
+        self.terrain.len() + self.edges.len() + self.items.len() + self.characters.len()
+    }
+}
+
+// the names in the mapping file are the names of the types
+fn key<T: std::fmt::Debug>(value: &T) -> String {
+    format!("{:?}", value)
+}
+
+// a plain square for anything the atlas has no sprite for, inset so it does not hide the tile under it
+fn square(cell: u32, color: Color, inset: u32) -> RgbaImage {
+    ImageBuffer::from_fn(cell, cell, |x, y| {
+        let inside = x >= inset && y >= inset && x + inset < cell && y + inset < cell;
+        if inside { Rgba([color.r, color.g, color.b, 255]) } else { Rgba([0, 0, 0, 0]) }
+    })
+}
+
+// a width by height tile screenshot centred on the player
+pub fn screenshot(world: &World, player: &Character, atlas: &Atlas, width: i32, height: i32) -> RgbaImage {
+    let (width, height) = (width.max(1), height.max(1));
+    let (left, top) = (player.x_position - width / 2, player.y_position - height / 2);
+    let cell = atlas.cell;
+    let theme = palette::current();
+    let map = &player.game_map;
+    let mut img = RgbaImage::from_pixel(width as u32 * cell, height as u32 * cell, Rgba([0, 0, 0, 255]));
+    let at = |x: i32, y: i32| ((x - left) as i64 * cell as i64, (y - top) as i64 * cell as i64);
+
+    // the background, with one tile more on each side so the edges of the window know their neighbours
+    map.prefetch(left - 1, top - 1, left + width, top + height);
+    let terrain: Vec<Vec<TerrainType>> = (top - 1..=top + height)
+        .map(|y| (left - 1..=left + width).map(|x| map.get_tile(x, y).terrain_type).collect())
+        .collect();
+    let terrain_at = |x: i32, y: i32| terrain[(y - top + 1) as usize][(x - left + 1) as usize];
+    for y in top..top + height {
+        for x in left..left + width {
+            let terrain_type = terrain_at(x, y);
+            let (px, py) = at(x, y);
+            match atlas.terrain.get(&key(&terrain_type)) {
+                Some(sprite) => imageops::replace(&mut img, sprite, px, py),
+                None => imageops::replace(&mut img, &square(cell, theme.terrain(terrain_type, map.get_tile(x, y).elevation), 0), px, py),
+            }
+        }
+    }
+
+    // the edges are drawn once the background is down, so a neighbour never paints over them
+    for y in top..top + height {
+        for x in left..left + width {
+            let name = key(&terrain_at(x, y));
+            let (px, py) = at(x, y);
+            for side in SIDES {
+                let (dx, dy) = side.get_offset();
+                let next = key(&terrain_at(x + dx, y + dy));
+                if let Some(sprite) = atlas.edges.get(&(name.clone(), next, side)) {
+                    imageops::overlay(&mut img, sprite, px, py);
+                }
+            }
+        }
+    }
+
+    // on a wrap-around world the window can run over the side of the map
+    let config = map.config();
+    let in_view = |x: i32, y: i32| {
+        (left..left + width).find(|vx| config.wrap_x(*vx) == x).filter(|_| y >= top && y < top + height)
+    };
+
+    // the midground, items lie on the overworld only
+    if player.depth == 0 {
+        for ((x, y), item) in &world.items_by_position {
+            let Some(vx) = in_view(*x, *y) else { continue };
+            let (px, py) = at(vx, *y);
+            match atlas.items.get(&key(&item.item_type)) {
+                Some(sprite) => imageops::overlay(&mut img, sprite, px, py),
+                None => imageops::overlay(&mut img, &square(cell, theme.item(&item.item_type), cell / 4), px, py),
+            }
+        }
+    }
+
+    // the foreground, the player last so it is never hidden
+    let mut characters: Vec<&Character> = world.entities.iter()
+        .filter_map(|entity| match entity {
+            GameEntity::Character(character) if character.depth == player.depth => Some(character),
+            _ => None,
+        })
+        .collect();
+    characters.sort_by_key(|character| character.name == player.name);
+    for character in characters {
+        let Some(vx) = in_view(character.x_position, character.y_position) else { continue };
+        let (px, py) = at(vx, character.y_position);
+        match atlas.characters.get(&key(&character.character_type)) {
+            Some(sprite) => imageops::overlay(&mut img, sprite, px, py),
+            None => imageops::overlay(&mut img, &square(cell, theme.character(&character.character_type), cell / 4), px, py),
+        }
+    }
+    img
+}
+
@@ -0,0 +1,229 @@
+// Path: src/status.rs
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::attributes::{self, Attribute};
+use crate::{Character, GameObject, TerrainType};
+
+// ===========================================================================
+// Status effects
+// lasting conditions on a character, such as being poisoned or wet, that count down a turn
+// at a time and may do something on every turn they last.
+// an effect put on a character that already has it stacks: it starts over, lasts longer,
+// or grows stronger, depending on the effect.
+// effects tick in a fixed order, the order of EffectKind, so the same effects always play out the same way.
+
+// how long the effects put on by the world last
+pub const EXHAUSTED_TURNS: i32 = 3;
+pub const WET_TURNS: i32 = 5;
+pub const POISON_TURNS: i32 = 5;
+pub const REGENERATING_TURNS: i32 = 10;
+pub const BLESSED_TURNS: i32 = 30;
+const REGENERATION_PER_TURN: i32 = 2;
+
+// in the order effects are applied each turn
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EffectKind {
+    // loses health each turn, more for each stack
+    Poisoned,
+    // too hungry or thirsty, loses health each turn and is weaker and clumsier
+    Exhausted,
+    // soaked through, clumsier
+    Wet,
+    // gains health each turn
+    Regenerating,
+    // a little better at everything
+    Blessed,
+}
+
+// what happens when an effect is put on a character that has it already
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stacking {
+    // back to the full length
+    Refresh,
+    // the new turns are added on
+    Extend,
+    // one stack stronger, up to a limit, back to the full length
+    Intensify(i32),
+}
+
+impl EffectKind {
+    pub fn stacking(&self) -> Stacking {
+        match self {
+            EffectKind::Poisoned => Stacking::Intensify(5),
+            EffectKind::Exhausted => Stacking::Refresh,
+            EffectKind::Wet => Stacking::Refresh,
+            EffectKind::Regenerating => Stacking::Extend,
+            EffectKind::Blessed => Stacking::Refresh,
+        }
+    }
+
+    // how the effect changes attributes, for each stack
+    pub fn modifiers(&self) -> &'static [(Attribute, i32)] {
+        match self {
+            EffectKind::Poisoned | EffectKind::Regenerating => &[],
+            EffectKind::Exhausted => &[(Attribute::Strength, -2), (Attribute::Dexterity, -2)],
+            EffectKind::Wet => &[(Attribute::Dexterity, -1)],
+            EffectKind::Blessed => &[(Attribute::Strength, 1), (Attribute::Dexterity, 1), (Attribute::Intelligence, 1)],
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StatusEffect {
+    pub kind: EffectKind,
+    pub turns: i32,
+    pub stacks: i32,
+}
+
+// Poisoned x2 for 3 turns
+impl fmt::Display for StatusEffect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.kind)?;
+        if self.stacks > 1 {
+            write!(f, " x{}", self.stacks)?;
+        }
+        write!(f, " for {} turns", self.turns)
+    }
+}
+
+// the character with an effect put on it for a number of turns, stacked with any it has already
+pub fn afflict(character: &Character, kind: EffectKind, turns: i32) -> Character {
+    let mut effects = (*character.effects).clone();
+    match effects.iter_mut().find(|effect| effect.kind == kind) {
+        Some(effect) => match kind.stacking() {
+            Stacking::Refresh => effect.turns = effect.turns.max(turns),
+            Stacking::Extend => effect.turns += turns,
+            Stacking::Intensify(most) => {
+                effect.turns = effect.turns.max(turns);
+                effect.stacks = (effect.stacks + 1).min(most);
+            }
+        },
+        None => {
+            println!("{} is {:?}", character.name, kind);
+            effects.push(StatusEffect { kind, turns, stacks: 1 });
+            effects.sort_by_key(|effect| effect.kind);
+        }
+    }
+    Character { effects: Arc::new(effects), ..character.clone() }
+}
+
+// the effects the character's state and surroundings put on it at the start of a turn
+pub fn from_surroundings(character: &Character) -> Character {
+    let mut character = character.clone();
+    if character.energy == 0 || character.hydration == 0 {
+        character = afflict(&character, EffectKind::Exhausted, EXHAUSTED_TURNS);
+    }
+    let terrain_type = character.get_tile(character.x_position, character.y_position).terrain_type;
+    if matches!(terrain_type, TerrainType::River | TerrainType::Lake | TerrainType::Swamp) {
+        character = afflict(&character, EffectKind::Wet, WET_TURNS);
+    }
+    character
+}
+
+// one turn of every effect, in order, then a turn off each, reporting those that wear off
+pub fn tick(character: &Character) -> Character {
+    let mut health = character.health;
+    for effect in character.effects.iter() {
+        match effect.kind {
+            EffectKind::Poisoned => health -= effect.stacks,
+            EffectKind::Exhausted => health -= 1,
+            EffectKind::Regenerating => health += REGENERATION_PER_TURN * effect.stacks,
+            EffectKind::Wet | EffectKind::Blessed => {}
+        }
+    }
+    let health = health.clamp(0, attributes::max_health(character).total.max(character.health));
+
+    let mut effects = Vec::new();
+    for effect in character.effects.iter() {
+        if effect.turns > 1 {
+            effects.push(StatusEffect { turns: effect.turns - 1, ..effect.clone() });
+        } else {
+            println!("{} is no longer {:?}", character.name, effect.kind);
+        }
+    }
+    Character { health, effects: Arc::new(effects), ..character.clone() }
+}
+
+// the effects on a character, for the `me` command
+pub fn describe(character: &Character) -> String {
+    if character.effects.is_empty() {
+        return "no effects".to_string();
+    }
+    let effects: Vec<String> = character.effects.iter().map(|effect| effect.to_string()).collect();
+    format!("effects: {}", effects.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{character, small_config};
+    use crate::{CharacterType, GameMap};
+
+    fn player() -> Character {
+        character("PlayerOne", CharacterType::Player, &GameMap::new(small_config(42)), 64, 64)
+    }
+
+    fn effect(character: &Character, kind: EffectKind) -> Option<&StatusEffect> {
+        character.effects.iter().find(|effect| effect.kind == kind)
+    }
+
+    #[test]
+    fn refreshing_goes_back_to_the_full_length() {
+        let player = afflict(&player(), EffectKind::Wet, WET_TURNS);
+        let drying = tick(&tick(&player));
+        assert_eq!(effect(&drying, EffectKind::Wet).unwrap().turns, WET_TURNS - 2);
+        let soaked = afflict(&drying, EffectKind::Wet, WET_TURNS);
+        assert_eq!(effect(&soaked, EffectKind::Wet), Some(&StatusEffect { kind: EffectKind::Wet, turns: WET_TURNS, stacks: 1 }));
+        // a shorter soaking does not cut it short
+        assert_eq!(effect(&afflict(&soaked, EffectKind::Wet, 1), EffectKind::Wet).unwrap().turns, WET_TURNS);
+    }
+
+    #[test]
+    fn extending_adds_the_turns_on() {
+        let player = afflict(&player(), EffectKind::Regenerating, 4);
+        let player = afflict(&tick(&player), EffectKind::Regenerating, 4);
+        assert_eq!(effect(&player, EffectKind::Regenerating), Some(&StatusEffect { kind: EffectKind::Regenerating, turns: 7, stacks: 1 }));
+    }
+
+    #[test]
+    fn intensifying_adds_a_stack_up_to_the_most() {
+        let Stacking::Intensify(most) = EffectKind::Poisoned.stacking() else { panic!("poison intensifies") };
+        assert_eq!(most, 5);
+        let poisoned = (0..8).fold(player(), |player, _| afflict(&player, EffectKind::Poisoned, POISON_TURNS));
+        assert_eq!(effect(&poisoned, EffectKind::Poisoned), Some(&StatusEffect { kind: EffectKind::Poisoned, turns: POISON_TURNS, stacks: most }));
+        assert_eq!(tick(&poisoned).health, poisoned.health - most);
+    }
+
+    #[test]
+    fn effects_stay_in_the_order_they_tick() {
+        let player = [EffectKind::Blessed, EffectKind::Wet, EffectKind::Poisoned, EffectKind::Regenerating, EffectKind::Exhausted]
+            .into_iter()
+            .fold(player(), |player, kind| afflict(&player, kind, 3));
+        let kinds: Vec<EffectKind> = player.effects.iter().map(|effect| effect.kind).collect();
+        assert_eq!(kinds, [EffectKind::Poisoned, EffectKind::Exhausted, EffectKind::Wet, EffectKind::Regenerating, EffectKind::Blessed]);
+        let kinds: Vec<EffectKind> = tick(&player).effects.iter().map(|effect| effect.kind).collect();
+        assert_eq!(kinds, [EffectKind::Poisoned, EffectKind::Exhausted, EffectKind::Wet, EffectKind::Regenerating, EffectKind::Blessed]);
+    }
+
+    #[test]
+    fn regenerating_stops_at_max_health() {
+        let player = player();
+        let most = attributes::max_health(&player).total;
+        let hurt = afflict(&Character { health: most - 3, ..player }, EffectKind::Regenerating, REGENERATING_TURNS);
+        let healed = (0..REGENERATING_TURNS).fold(hurt, |player, _| tick(&player));
+        assert_eq!(healed.health, most);
+        assert!(healed.effects.is_empty());
+    }
+
+    #[test]
+    fn effects_wear_off_one_turn_at_a_time() {
+        let player = afflict(&player(), EffectKind::Exhausted, 2);
+        let once = tick(&player);
+        assert_eq!(once.health, player.health - 1);
+        assert_eq!(effect(&once, EffectKind::Exhausted).unwrap().turns, 1);
+        assert!(tick(&once).effects.is_empty());
+        assert_eq!(describe(&player), "effects: Exhausted for 2 turns");
+    }
+}
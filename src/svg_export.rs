@@ -0,0 +1,297 @@
+// Path: src/svg_export.rs
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use rayon::prelude::*;
+
+use crate::palette;
+use crate::{GameEntity, GameMap, Item, TerrainType, World};
+
+// ===========================================================================
+// SVG export
+// the map as lines instead of pixels, for print and design tools.
+// the coast and the contours are traced with marching squares over the heights sampled from the map,
+// every few tiles, then the traced lines are thinned out with Douglas-Peucker.
+// the coast, contours, characters and items each go in their own group.
+
+pub const DEFAULT_STEP: i32 = 2;
+pub const DEFAULT_CONTOUR_INTERVAL: f64 = 5.0;
+// a simplified line strays no further than this from the traced one, in tiles
+const TOLERANCE: f64 = 0.75;
+// the coast is where the ground rises out of the sea, see Tile::from_elevation
+const SHORE: f64 = 1.0;
+
+type Point = (f64, f64);
+
+// the points where a line crosses a cell edge are found by the edge they are on,
+// the cell corner it starts at and whether it runs across or down
+type EdgeKey = (i32, i32, bool);
+
+// values sampled every step tiles across the map
+struct Field {
+    width: i32,
+    height: i32,
+    step: i32,
+    values: Vec<f64>,
+}
+
+impl Field {
+    fn sample<F>(map: &GameMap, step: i32, value: F) -> Self
+        where F: Fn(i32, i32) -> f64 + Sync
+    {
+        let config = map.config();
+        let width = (config.width - 1) / step + 1;
+        let height = (config.height - 1) / step + 1;
+        let values = (0..width * height).into_par_iter()
+            .map(|index| value((index % width) * step, (index / width) * step))
+            .collect();
+        Field { width, height, step, values }
+    }
+
+    fn get(&self, x: i32, y: i32) -> f64 {
+        self.values[(y * self.width + x) as usize]
+    }
+}
+
+// write the map as an svg file, with contours every contour_interval of height, sampling the map every step tiles
+pub fn export(world: &World, filename: &str, contour_interval: f64, step: i32) -> io::Result<()> {
+    let map = &world.game_map;
+    let config = map.config();
+    let step = step.max(1);
+
+    let heights = Field::sample(map, step, |x, y| map.elevation_at(x, y));
+    let coast = trace(&heights, SHORE);
+
+    // a contour for every level between the shore and the highest ground
+    let highest = heights.values.iter().cloned().fold(0.0, f64::max);
+    let interval = contour_interval.max(0.5);
+    let levels: Vec<f64> = (1..).map(|n| n as f64 * interval).take_while(|level| *level < highest)
+        .filter(|level| *level != SHORE)
+        .collect();
+    let contours: Vec<(f64, Vec<Vec<Point>>)> = levels.par_iter()
+        .map(|&level| (level, trace(&heights, level)))
+        .collect();
+
+    let theme = palette::current();
+    let mut svg = String::new();
+    let _ = writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+                     config.width, config.height, config.width, config.height);
+
+    // the coast in a deeper shade of the sea, the contours in the colour of bare earth
+    let _ = writeln!(svg, r#"<g id="coast" fill="none" stroke="{}" stroke-width="1.5">"#,
+                     theme.terrain(TerrainType::Water, 0).darker().to_hex());
+    for line in &coast {
+        path(&mut svg, line, "");
+    }
+    svg.push_str("</g>\n");
+
+    let _ = writeln!(svg, r#"<g id="contours" fill="none" stroke="{}" stroke-width="0.5">"#,
+                     theme.terrain(TerrainType::Earth, 0).to_hex());
+    for (level, lines) in &contours {
+        for line in lines {
+            path(&mut svg, line, &format!(r#" data-elevation="{}""#, level));
+        }
+    }
+    svg.push_str("</g>\n");
+
+    svg.push_str("<g id=\"items\">\n");
+    let mut items: Vec<(&(i32, i32), &Item)> = world.items_by_position.iter().collect();
+    items.sort_by_key(|(position, _)| **position);
+    for ((x, y), item) in items {
+        let _ = writeln!(svg, r#"<circle cx="{}" cy="{}" r="1.5" fill="{}"/>"#, x, y, theme.item(&item.item_type).to_hex());
+    }
+    svg.push_str("</g>\n");
+
+    let _ = writeln!(svg, r#"<g id="characters" font-family="sans-serif" font-size="12">"#);
+    for entity in &world.entities {
+        if let GameEntity::Character(character) = entity {
+            if character.depth == 0 {
+                let (x, y) = (character.x_position, character.y_position);
+                let _ = writeln!(svg, r#"<g fill="{}"><circle cx="{}" cy="{}" r="3"/><text x="{}" y="{}">{}</text></g>"#,
+                                 theme.character(&character.character_type).to_hex(), x, y, x + 5, y - 5, escape(&character.name));
+            }
+        }
+    }
+    svg.push_str("</g>\n</svg>\n");
+
+    fs::write(filename, svg)
+}
+
+fn path(svg: &mut String, line: &[Point], attributes: &str) {
+    let mut d = String::new();
+    for (index, (x, y)) in line.iter().enumerate() {
+        let _ = write!(d, "{}{:.1},{:.1}", if index == 0 { "M" } else { " L" }, x, y);
+    }
+    let _ = writeln!(svg, r#"<path d="{}"{}/>"#, d, attributes);
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+// the lines where the field crosses level, in tiles, joined up and simplified
+fn trace(field: &Field, level: f64) -> Vec<Vec<Point>> {
+    let segments = march(field, level);
+    join(segments).into_iter()
+        .map(|line| {
+            let line: Vec<Point> = line.iter().map(|&key| crossing(field, level, key)).collect();
+            simplify(&line, TOLERANCE)
+        })
+        .filter(|line| line.len() >= 2)
+        .collect()
+}
+
+// the bits of line in each cell of four samples, as pairs of the edges they run between
+fn march(field: &Field, level: f64) -> Vec<(EdgeKey, EdgeKey)> {
+    (0..field.height - 1).into_par_iter()
+        .flat_map_iter(|y| (0..field.width - 1).map(move |x| (x, y)))
+        .flat_map_iter(|(x, y)| {
+            let above = |cx, cy| field.get(cx, cy) > level;
+            let corners = [above(x, y), above(x + 1, y), above(x + 1, y + 1), above(x, y + 1)];
+            let case = corners.iter().enumerate().fold(0, |case, (bit, inside)| case | ((*inside as usize) << bit));
+            let top = (x, y, true);
+            let right = (x + 1, y, false);
+            let bottom = (x, y + 1, true);
+            let left = (x, y, false);
+            // the middle of the cell decides which way the two saddle cases join up
+            let centre_above = (field.get(x, y) + field.get(x + 1, y) + field.get(x + 1, y + 1) + field.get(x, y + 1)) / 4.0 > level;
+            let pairs: Vec<(EdgeKey, EdgeKey)> = match case {
+                0 | 15 => vec![],
+                1 | 14 => vec![(left, top)],
+                2 | 13 => vec![(top, right)],
+                3 | 12 => vec![(left, right)],
+                4 | 11 => vec![(right, bottom)],
+                6 | 9 => vec![(top, bottom)],
+                7 | 8 => vec![(left, bottom)],
+                5 if centre_above => vec![(left, bottom), (top, right)],
+                5 => vec![(left, top), (right, bottom)],
+                10 if centre_above => vec![(left, top), (right, bottom)],
+                _ => vec![(left, bottom), (top, right)],
+            };
+            pairs
+        })
+        .collect()
+}
+
+// where along an edge the field crosses level, in tiles
+fn crossing(field: &Field, level: f64, (x, y, across): EdgeKey) -> Point {
+    let (x1, y1) = if across { (x + 1, y) } else { (x, y + 1) };
+    let (a, b) = (field.get(x, y), field.get(x1, y1));
+    let t = if (b - a).abs() < f64::EPSILON { 0.5 } else { ((level - a) / (b - a)).clamp(0.0, 1.0) };
+    let step = field.step as f64;
+    ((x as f64 + (x1 - x) as f64 * t) * step, (y as f64 + (y1 - y) as f64 * t) * step)
+}
+
+// string the pieces together into lines, each edge is shared by at most two pieces
+fn join(segments: Vec<(EdgeKey, EdgeKey)>) -> Vec<Vec<EdgeKey>> {
+    let mut ends: HashMap<EdgeKey, Vec<usize>> = HashMap::new();
+    for (index, (a, b)) in segments.iter().enumerate() {
+        ends.entry(*a).or_default().push(index);
+        ends.entry(*b).or_default().push(index);
+    }
+    let mut used = vec![false; segments.len()];
+    let mut lines = Vec::new();
+    for start in 0..segments.len() {
+        if used[start] {
+            continue;
+        }
+        used[start] = true;
+        let mut line = vec![segments[start].0, segments[start].1];
+        // grow the line from its tail, then turn it round and grow it from the other end
+        for _ in 0..2 {
+            loop {
+                let tail = line[line.len() - 1];
+                let next = ends[&tail].iter().copied().find(|&index| !used[index]);
+                let Some(index) = next else { break };
+                used[index] = true;
+                let (a, b) = segments[index];
+                line.push(if a == tail { b } else { a });
+            }
+            line.reverse();
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+// Douglas-Peucker, keep the point furthest from the straight line between the ends
+// if it is more than tolerance away, and do the same on each side of it
+fn simplify(line: &[Point], tolerance: f64) -> Vec<Point> {
+    if line.len() < 3 {
+        return line.to_vec();
+    }
+    let (first, last) = (line[0], line[line.len() - 1]);
+    let (furthest, distance) = line.iter().enumerate().skip(1).take(line.len() - 2)
+        .map(|(index, point)| (index, distance_to_line(*point, first, last)))
+        .fold((0, 0.0), |best, next| if next.1 > best.1 { next } else { best });
+    if distance <= tolerance {
+        return vec![first, last];
+    }
+    let mut left = simplify(&line[..=furthest], tolerance);
+    let right = simplify(&line[furthest..], tolerance);
+    left.pop();
+    left.extend(right);
+    left
+}
+
+fn distance_to_line((x, y): Point, (x1, y1): Point, (x2, y2): Point) -> f64 {
+    let (dx, dy) = (x2 - x1, y2 - y1);
+    let length = dx.hypot(dy);
+    if length < f64::EPSILON {
+        return (x - x1).hypot(y - y1);
+    }
+    ((x - x1) * dy - (y - y1) * dx).abs() / length
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a field of one step per tile from its rows, top row first
+    fn field(rows: &[&[f64]]) -> Field {
+        Field { width: rows[0].len() as i32, height: rows.len() as i32, step: 1, values: rows.concat() }
+    }
+
+    #[test]
+    fn points_on_a_straight_line_simplify_to_its_ends() {
+        let line: Vec<Point> = (0..=10).map(|i| (i as f64, 2.0 * i as f64 + 1.0)).collect();
+        assert_eq!(simplify(&line, TOLERANCE), vec![(0.0, 1.0), (10.0, 21.0)]);
+        let flat = [(0.0, 0.0), (5.0, 0.0), (5.0, 0.0), (10.0, 0.0)];
+        assert_eq!(simplify(&flat, TOLERANCE), vec![(0.0, 0.0), (10.0, 0.0)]);
+    }
+
+    #[test]
+    fn simplify_keeps_corners_and_drops_wobbles() {
+        let line = [(0.0, 0.0), (2.0, 0.5), (4.0, 0.0), (4.0, 2.0), (4.2, 4.0), (4.0, 6.0)];
+        assert_eq!(simplify(&line, TOLERANCE), vec![(0.0, 0.0), (4.0, 0.0), (4.0, 6.0)]);
+        assert_eq!(simplify(&line[..2], TOLERANCE), line[..2].to_vec());
+    }
+
+    #[test]
+    fn saddles_join_up_by_the_middle_of_the_cell() {
+        let (top, right, bottom, left) = ((0, 0, true), (1, 0, false), (0, 1, true), (0, 0, false));
+        // the high corners are top left and bottom right
+        assert_eq!(march(&field(&[&[2.0, 0.0], &[0.0, 2.0]]), 0.5), vec![(left, bottom), (top, right)]);
+        assert_eq!(march(&field(&[&[1.0, 0.0], &[0.0, 1.0]]), 0.6), vec![(left, top), (right, bottom)]);
+        // the high corners are top right and bottom left
+        assert_eq!(march(&field(&[&[0.0, 2.0], &[2.0, 0.0]]), 0.5), vec![(left, top), (right, bottom)]);
+        assert_eq!(march(&field(&[&[0.0, 1.0], &[1.0, 0.0]]), 0.6), vec![(left, bottom), (top, right)]);
+    }
+
+    #[test]
+    fn a_saddle_traces_two_lines_through_the_edge_crossings() {
+        let lines = trace(&field(&[&[2.0, 0.0], &[0.0, 2.0]]), 0.5);
+        assert_eq!(lines, vec![vec![(0.0, 0.75), (0.25, 1.0)], vec![(0.75, 0.0), (1.0, 0.25)]]);
+    }
+
+    #[test]
+    fn a_hill_traces_one_closed_line() {
+        let lines = trace(&field(&[&[0.0, 0.0, 0.0], &[0.0, 2.0, 0.0], &[0.0, 0.0, 0.0]]), 1.0);
+        assert_eq!(lines.len(), 1);
+        let line = &lines[0];
+        assert_eq!(line.first(), line.last());
+        assert!(line.len() >= 3);
+    }
+}
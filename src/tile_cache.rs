@@ -0,0 +1,243 @@
+// Path: src/tile_cache.rs
+
+use std::cmp::max;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use once_cell::sync::OnceCell;
+use rayon::prelude::*;
+
+use crate::Tile;
+
+// ===========================================================================
+// Tile cache
+// tiles are derived from noise which is slow to sample, so the map remembers them.
+// the world is split into square chunks, a chunk is generated in one go the first
+// time any of its tiles is asked for, and the least recently used chunks are
+// dropped once the cache is full.
+
+pub const CHUNK_SIZE: i32 = 64;
+
+// enough chunks to hold a whole 2048x2048 world
+pub const DEFAULT_MAX_CHUNKS: usize = 1024;
+
+struct Chunk {
+    // filled in by whichever thread asks for the chunk first, the others wait for it
+    tiles: OnceCell<Vec<Tile>>,
+    last_used: AtomicU64,
+}
+
+pub struct TileCache {
+    chunks: RwLock<HashMap<(i32, i32), Arc<Chunk>>>,
+    max_chunks: usize,
+    // ticks on every lookup, used to find the least recently used chunk
+    clock: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+// a snapshot of how well the cache is doing
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub resident_chunks: usize,
+    pub max_chunks: usize,
+}
+
+impl CacheStats {
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Tile cache {{ hits: {}, misses: {}, hit rate: {:.2}%, evictions: {}, chunks: {}/{} }}",
+               self.hits, self.misses, self.hit_rate() * 100.0, self.evictions,
+               self.resident_chunks, self.max_chunks)
+    }
+}
+
+impl TileCache {
+    pub fn new(max_chunks: usize) -> Self {
+        TileCache {
+            chunks: RwLock::new(HashMap::new()),
+            max_chunks: max(1, max_chunks),
+            clock: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    // which chunk x,y falls in
+    pub fn chunk_of(x: i32, y: i32) -> (i32, i32) {
+        (x.div_euclid(CHUNK_SIZE), y.div_euclid(CHUNK_SIZE))
+    }
+
+    // return the tile at x,y, generating its chunk if it is not cached
+    pub fn get_or_generate<F>(&self, x: i32, y: i32, generate: F) -> Tile
+        where F: Fn(i32, i32) -> Tile
+    {
+        let (chunk_x, chunk_y) = Self::chunk_of(x, y);
+        let chunk = self.chunk(chunk_x, chunk_y);
+        let tiles = chunk.tiles.get_or_init(|| Self::generate_chunk(chunk_x, chunk_y, &generate));
+        let local_x = x - chunk_x * CHUNK_SIZE;
+        let local_y = y - chunk_y * CHUNK_SIZE;
+        tiles[(local_y * CHUNK_SIZE + local_x) as usize].clone()
+    }
+
+    // generate every chunk touching the rectangle, spread over the rayon thread pool
+    pub fn prefetch<F>(&self, x0: i32, y0: i32, x1: i32, y1: i32, generate: F)
+        where F: Fn(i32, i32) -> Tile + Sync
+    {
+        let (first_x, first_y) = Self::chunk_of(x0, y0);
+        let (last_x, last_y) = Self::chunk_of(x1, y1);
+        let keys: Vec<(i32, i32)> = (first_y..=last_y)
+            .flat_map(|chunk_y| (first_x..=last_x).map(move |chunk_x| (chunk_x, chunk_y)))
+            .take(self.max_chunks)
+            .collect();
+        keys.par_iter().for_each(|&(chunk_x, chunk_y)| {
+            let chunk = self.chunk(chunk_x, chunk_y);
+            chunk.tiles.get_or_init(|| Self::generate_chunk(chunk_x, chunk_y, &generate));
+        });
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            resident_chunks: self.chunks.read().unwrap().len(),
+            max_chunks: self.max_chunks,
+        }
+    }
+
+    // find the chunk, or make an empty slot for it, evicting if the cache is full
+    fn chunk(&self, chunk_x: i32, chunk_y: i32) -> Arc<Chunk> {
+        let now = self.clock.fetch_add(1, Ordering::Relaxed);
+
+        if let Some(chunk) = self.chunks.read().unwrap().get(&(chunk_x, chunk_y)) {
+            chunk.last_used.store(now, Ordering::Relaxed);
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return chunk.clone();
+        }
+
+        let mut chunks = self.chunks.write().unwrap();
+        // another thread may have made the slot while we waited for the lock
+        if let Some(chunk) = chunks.get(&(chunk_x, chunk_y)) {
+            chunk.last_used.store(now, Ordering::Relaxed);
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return chunk.clone();
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        if chunks.len() >= self.max_chunks {
+            let oldest = chunks.iter()
+                .min_by_key(|(_, chunk)| chunk.last_used.load(Ordering::Relaxed))
+                .map(|(key, _)| *key);
+            if let Some(oldest) = oldest {
+                chunks.remove(&oldest);
+                self.evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let chunk = Arc::new(Chunk {
+            tiles: OnceCell::new(),
+            last_used: AtomicU64::new(now),
+        });
+        chunks.insert((chunk_x, chunk_y), chunk.clone());
+        chunk
+    }
+
+    // generate the tiles of one chunk in row order
+    fn generate_chunk<F>(chunk_x: i32, chunk_y: i32, generate: &F) -> Vec<Tile>
+        where F: Fn(i32, i32) -> Tile
+    {
+        let origin_x = chunk_x * CHUNK_SIZE;
+        let origin_y = chunk_y * CHUNK_SIZE;
+        (0..CHUNK_SIZE * CHUNK_SIZE)
+            .map(|i| generate(origin_x + i % CHUNK_SIZE, origin_y + i / CHUNK_SIZE))
+            .collect()
+    }
+}
+
+impl fmt::Debug for TileCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.stats())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::zones::Zone;
+    use crate::TerrainType;
+
+    fn tile(x: i32, y: i32) -> Tile {
+        Tile { terrain_type: TerrainType::Grass, x_position: x, y_position: y, elevation: 0, moisture: 0, temperature: 0, depth: 0, zone: Zone::Wild, spawn: None }
+    }
+
+    // the tile at x,y from the cache, counting the chunks generated
+    fn lookup(cache: &TileCache, generated: &Cell<usize>, x: i32, y: i32) -> Tile {
+        cache.get_or_generate(x, y, |x, y| {
+            if (x, y) == (x.div_euclid(CHUNK_SIZE) * CHUNK_SIZE, y.div_euclid(CHUNK_SIZE) * CHUNK_SIZE) {
+                generated.set(generated.get() + 1);
+            }
+            tile(x, y)
+        })
+    }
+
+    #[test]
+    fn tiles_come_from_their_chunk_and_are_counted() {
+        let cache = TileCache::new(4);
+        let generated = Cell::new(0);
+        assert_eq!(lookup(&cache, &generated, 5, 7), tile(5, 7));
+        assert_eq!(lookup(&cache, &generated, CHUNK_SIZE - 1, CHUNK_SIZE - 1), tile(CHUNK_SIZE - 1, CHUNK_SIZE - 1));
+        assert_eq!(lookup(&cache, &generated, -1, 0), tile(-1, 0));
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions, stats.resident_chunks), (1, 2, 0, 2));
+        assert_eq!(generated.get(), 2);
+    }
+
+    #[test]
+    fn never_holds_more_than_max_chunks() {
+        let cache = TileCache::new(2);
+        let generated = Cell::new(0);
+        for chunk in 0..5 {
+            lookup(&cache, &generated, chunk * CHUNK_SIZE, 0);
+            assert!(cache.stats().resident_chunks <= 2);
+        }
+        let stats = cache.stats();
+        assert_eq!((stats.misses, stats.evictions, stats.resident_chunks), (5, 3, 2));
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_chunk() {
+        let cache = TileCache::new(2);
+        let generated = Cell::new(0);
+        let (a, b, c) = (0, CHUNK_SIZE, 2 * CHUNK_SIZE);
+        lookup(&cache, &generated, a, 0);
+        lookup(&cache, &generated, b, 0);
+        // a is used again, so b is now the oldest and makes room for c
+        lookup(&cache, &generated, a, 0);
+        lookup(&cache, &generated, c, 0);
+        assert_eq!(generated.get(), 3);
+        lookup(&cache, &generated, a, 0);
+        assert_eq!(generated.get(), 3);
+        lookup(&cache, &generated, b, 0);
+        assert_eq!(generated.get(), 4);
+        assert_eq!(cache.stats().evictions, 2);
+    }
+}
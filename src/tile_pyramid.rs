@@ -0,0 +1,166 @@
+// Path: src/tile_pyramid.rs
+
+use std::fs;
+use std::path::Path;
+use image::{ImageBuffer, ImageResult, Rgb, RgbImage};
+use rayon::prelude::*;
+
+use crate::{Color, GameMap, GameObject};
+
+// ===========================================================================
+// Tile pyramid
+// a big world is exported as a pyramid of small images, the way web maps are,
+// so it can be browsed without ever loading the whole map at once.
+// the deepest zoom level has one pixel per tile of the map, each level above
+// it is half the size, down to level 0 where the whole world fits one image.
+// images are saved as dir/z/x/y.png next to a small html viewer.
+
+pub const TILE_SIZE: i32 = 256;
+
+// the deepest zoom level for a map, where one pixel is one tile
+pub fn max_zoom(map: &GameMap) -> u32 {
+    let config = map.config();
+    let mut zoom = 0;
+    while (TILE_SIZE << zoom) < config.width.max(config.height) {
+        zoom += 1;
+    }
+    zoom
+}
+
+// write every level of the pyramid and the viewer into dir, returns how many images were saved
+pub fn export(map: &GameMap, dir: &str) -> ImageResult<usize> {
+    let dir = Path::new(dir);
+    let top = max_zoom(map);
+    let mut saved = 0;
+
+    // the deepest level is drawn from the map, each level above from the one below it
+    for zoom in (0..=top).rev() {
+        let across = 1 << zoom;
+        for x in 0..across {
+            fs::create_dir_all(dir.join(zoom.to_string()).join(x.to_string()))?;
+        }
+        let images: Vec<(i32, i32)> = (0..across).flat_map(|x| (0..across).map(move |y| (x, y))).collect();
+        saved += images.par_iter()
+            .map(|&(x, y)| {
+                let img = if zoom == top { draw(map, x, y) } else { shrink(dir, zoom + 1, x, y) };
+                img.save(path(dir, zoom, x, y))
+            })
+            .collect::<ImageResult<Vec<()>>>()?
+            .len();
+    }
+
+    fs::write(dir.join("index.html"), viewer(top))?;
+    Ok(saved)
+}
+
+fn path(dir: &Path, zoom: u32, x: i32, y: i32) -> std::path::PathBuf {
+    dir.join(zoom.to_string()).join(x.to_string()).join(format!("{}.png", y))
+}
+
+// one image of the deepest level, off the edge of the map is left black
+fn draw(map: &GameMap, tile_x: i32, tile_y: i32) -> RgbImage {
+    let (left, top) = (tile_x * TILE_SIZE, tile_y * TILE_SIZE);
+    map.prefetch(left, top, left + TILE_SIZE - 1, top + TILE_SIZE - 1);
+    ImageBuffer::from_fn(TILE_SIZE as u32, TILE_SIZE as u32, |x, y| {
+        let (x, y) = (left + x as i32, top + y as i32);
+        if map.config().in_bounds(x, y) { map.get_tile(x, y).color().to_rgb() } else { Color::BLACK.to_rgb() }
+    })
+}
+
+// one image made from the four below it, each block of four pixels averaged into one
+fn shrink(dir: &Path, below: u32, tile_x: i32, tile_y: i32) -> RgbImage {
+    let mut img = ImageBuffer::from_pixel(TILE_SIZE as u32, TILE_SIZE as u32, Color::BLACK.to_rgb());
+    let half = TILE_SIZE as u32 / 2;
+    for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+        let Ok(child) = image::open(path(dir, below, tile_x * 2 + dx, tile_y * 2 + dy)) else { continue };
+        let child = child.to_rgb8();
+        for y in 0..half {
+            for x in 0..half {
+                let mut sum = [0u32; 3];
+                for (cx, cy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let pixel = child.get_pixel(x * 2 + cx, y * 2 + cy);
+                    for channel in 0..3 {
+                        sum[channel] += pixel[channel] as u32;
+                    }
+                }
+                let average = Rgb([(sum[0] / 4) as u8, (sum[1] / 4) as u8, (sum[2] / 4) as u8]);
+                img.put_pixel(dx as u32 * half + x, dy as u32 * half + y, average);
+            }
+        }
+    }
+    img
+}
+
+// a page that shows the pyramid, drag to move around and scroll to zoom
+fn viewer(max_zoom: u32) -> String {
+    VIEWER.replace("{MAX_ZOOM}", &max_zoom.to_string()).replace("{TILE_SIZE}", &TILE_SIZE.to_string())
+}
+
+const VIEWER: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>World map</title>
+<style>
+  html, body { margin: 0; height: 100%; overflow: hidden; background: #000; }
+  #map { position: absolute; inset: 0; cursor: grab; }
+  #map img { position: absolute; image-rendering: pixelated; }
+  #zoom { position: absolute; top: 8px; left: 8px; color: #fff; font: 14px sans-serif; }
+</style>
+</head>
+<body>
+<div id="map"></div>
+<div id="zoom"></div>
+<script>
+const MAX_ZOOM = {MAX_ZOOM};
+const TILE = {TILE_SIZE};
+const map = document.getElementById("map");
+let zoom = 0;
+// the world position, in pixels at the current zoom, that sits in the middle of the window
+let centre = { x: TILE / 2, y: TILE / 2 };
+
+function draw() {
+  map.innerHTML = "";
+  const across = 1 << zoom;
+  const left = centre.x - map.clientWidth / 2;
+  const top = centre.y - map.clientHeight / 2;
+  for (let x = Math.max(0, Math.floor(left / TILE)); x < Math.min(across, Math.ceil((left + map.clientWidth) / TILE)); x++) {
+    for (let y = Math.max(0, Math.floor(top / TILE)); y < Math.min(across, Math.ceil((top + map.clientHeight) / TILE)); y++) {
+      const img = document.createElement("img");
+      img.src = zoom + "/" + x + "/" + y + ".png";
+      img.style.left = (x * TILE - left) + "px";
+      img.style.top = (y * TILE - top) + "px";
+      map.appendChild(img);
+    }
+  }
+  document.getElementById("zoom").textContent = "zoom " + zoom + " of " + MAX_ZOOM;
+}
+
+let drag = null;
+map.addEventListener("mousedown", e => { drag = { x: e.clientX, y: e.clientY }; });
+window.addEventListener("mouseup", () => { drag = null; });
+window.addEventListener("mousemove", e => {
+  if (!drag) return;
+  centre.x -= e.clientX - drag.x;
+  centre.y -= e.clientY - drag.y;
+  drag = { x: e.clientX, y: e.clientY };
+  draw();
+});
+map.addEventListener("wheel", e => {
+  e.preventDefault();
+  const next = Math.min(MAX_ZOOM, Math.max(0, zoom + (e.deltaY < 0 ? 1 : -1)));
+  if (next === zoom) return;
+  // keep the point under the mouse where it is
+  const scale = Math.pow(2, next - zoom);
+  const mouse = { x: e.clientX - map.clientWidth / 2, y: e.clientY - map.clientHeight / 2 };
+  centre.x = (centre.x + mouse.x) * scale - mouse.x;
+  centre.y = (centre.y + mouse.y) * scale - mouse.y;
+  zoom = next;
+  draw();
+}, { passive: false });
+window.addEventListener("resize", draw);
+draw();
+</script>
+</body>
+</html>
+"#;
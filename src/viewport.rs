@@ -0,0 +1,108 @@
+// Path: src/viewport.rs
+
+use std::collections::HashMap;
+
+use crate::fov::Seen;
+use crate::palette;
+use crate::{Character, Color, GameEntity, GameObject, ItemType, TerrainType, World};
+
+// ===========================================================================
+// Viewport
+// the part of the map around the player drawn as text in the terminal.
+// each tile is a glyph in the colour it has on the map image,
+// with characters and items drawn over the top.
+// with fog on, only what the player has seen is drawn, and what is out of sight is dimmed.
+
+pub const DEFAULT_WIDTH: i32 = 60;
+pub const DEFAULT_HEIGHT: i32 = 20;
+// the biggest window that is worth drawing in a terminal
+pub const MAX_WIDTH: i32 = 200;
+pub const MAX_HEIGHT: i32 = 100;
+
+const RESET: &str = "\x1b[0m";
+
+// the letter a terrain is drawn with
+pub fn glyph(terrain_type: TerrainType) -> char {
+    match terrain_type {
+        TerrainType::Limbo => ' ',
+        TerrainType::Boundary => 'X',
+        TerrainType::Earth => ',',
+        TerrainType::Grass => '.',
+        TerrainType::Beach => ':',
+        TerrainType::Water => '~',
+        TerrainType::Mountain => '^',
+        TerrainType::Forest => 'T',
+        TerrainType::Desert => '\'',
+        TerrainType::Tundra => '-',
+        TerrainType::Swamp => '"',
+        TerrainType::Jungle => '&',
+        TerrainType::Snow => '*',
+        TerrainType::River => '~',
+        TerrainType::Lake => '~',
+        TerrainType::Wall => '#',
+        TerrainType::Floor => '.',
+        TerrainType::Door => '+',
+        TerrainType::StairsUp => '<',
+        TerrainType::StairsDown => '>',
+        TerrainType::City => 'H',
+        TerrainType::Road => '=',
+        TerrainType::Bridge => '=',
+    }
+}
+
+fn item_glyph(item_type: &ItemType) -> char {
+    match item_type {
+        ItemType::Food => '%',
+        ItemType::Weapon => '/',
+        ItemType::Potion => '!',
+        ItemType::Quit => '?',
+    }
+}
+
+// a width by height window of the map centred on the player, one line of text per row
+pub fn render(world: &World, player: &Character, width: i32, height: i32, fog: bool) -> String {
+    let width = width.clamp(1, MAX_WIDTH);
+    let height = height.clamp(1, MAX_HEIGHT);
+    let (left, top) = (player.x_position - width / 2, player.y_position - height / 2);
+    let theme = palette::current();
+
+    // what is drawn over the terrain, characters win over items
+    let mut overlay: HashMap<(i32, i32), (char, Color)> = HashMap::new();
+    // items lie on the overworld only
+    if player.depth == 0 {
+        for ((x, y), item) in &world.items_by_position {
+            overlay.insert((*x, *y), (item_glyph(&item.item_type), theme.item(&item.item_type)));
+        }
+    }
+    for entity in &world.entities {
+        if let GameEntity::Character(character) = entity {
+            if character.depth == player.depth {
+                overlay.insert((character.x_position, character.y_position), (character.facing.arrow(), theme.character(&character.character_type)));
+            }
+        }
+    }
+
+    player.game_map.prefetch(left, top, left + width - 1, top + height - 1);
+    let mut text = String::new();
+    for y in top..top + height {
+        for x in left..left + width {
+            // on a wrap-around world the window can run over the side of the map
+            let seen = if fog { player.seen(x, y) } else { Seen::Visible };
+            let (letter, color) = match overlay.get(&(player.game_map.config().wrap_x(x), y)) {
+                Some(&(letter, color)) if seen == Seen::Visible => (letter, color),
+                _ => {
+                    let tile = player.get_tile(x, y);
+                    match seen.shade(theme.terrain(tile.terrain_type, tile.elevation)) {
+                        Some(color) => (glyph(tile.terrain_type), color),
+                        None => (' ', Color::BLACK),
+                    }
+                }
+            };
+            text.push_str(&color.to_ansi());
+            text.push(letter);
+        }
+        text.push_str(RESET);
+        text.push('\n');
+    }
+    text
+}
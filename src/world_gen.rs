@@ -0,0 +1,237 @@
+// Path: src/world_gen.rs
+
+use std::str::FromStr;
+use noise::Perlin;
+
+// ===========================================================================
+// World generation settings
+// everything that shapes a generated world lives in the config,
+// so the same config always rebuilds the same world tile for tile.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorldGenConfig {
+    // seed for the noise generators
+    pub seed: u32,
+    pub width: i32,
+    pub height: i32,
+    // the layout of land and sea
+    pub shape: WorldShape,
+    // how many islands an archipelago has
+    pub island_count: usize,
+    // tiles closer than this to the west and north edges of the map are world boundary,
+    // and closer than the far margin to the east and south edges
+    pub boundary_margin: i32,
+    pub far_boundary_margin: i32,
+    // height of the noise field above the shoreline, tiles at or below 0 are water
+    pub sea_level: f64,
+    // maximum height of the noise field at the centre of the map
+    pub elevation_scale: f64,
+    // tiles at or above this elevation are mountains, the land is never higher than elevation_scale - sea_level
+    pub mountain_threshold: i32,
+    // shrinks the distance used for the centre falloff of an island, bigger values give a smaller island
+    pub falloff_margin: f64,
+    // how many moisture and temperature blobs fit across the map, bigger values give smaller biomes
+    pub climate_frequency: f64,
+    // how many rivers to run from the high ground to the sea
+    pub river_count: usize,
+    // a lake that grows this big without finding a way out stops its river
+    pub max_lake_size: usize,
+    // wear the height field down before the tiles are classified, off when None
+    pub erosion: Option<ErosionConfig>,
+    // how many villages, towns and cities to build
+    pub settlement_count: usize,
+    // how many spawn points of each kind, monsters, items, characters and NPCs
+    pub spawn_points: usize,
+    // how many levels of caves and dungeons lie under the island
+    pub dungeon_levels: i32,
+    // how many stairs lead down from the overworld, and from each level to the next
+    pub cave_entrances: usize,
+    // size of each underground level
+    pub dungeon_width: i32,
+    pub dungeon_height: i32,
+}
+
+impl Default for WorldGenConfig {
+    fn default() -> Self {
+        WorldGenConfig {
+            seed: 7243,
+            width: 2048,
+            height: 2048,
+            shape: WorldShape::Island,
+            island_count: 7,
+            boundary_margin: 4,
+            far_boundary_margin: 8,
+            sea_level: 20.0,
+            elevation_scale: 60.0,
+            // half way up from the shore to the highest the land can reach
+            mountain_threshold: 20,
+            falloff_margin: 600.0,
+            climate_frequency: 4.0,
+            river_count: 12,
+            max_lake_size: 4000,
+            erosion: None,
+            settlement_count: 8,
+            spawn_points: 200,
+            dungeon_levels: 3,
+            cave_entrances: 3,
+            dungeon_width: 120,
+            dungeon_height: 80,
+        }
+    }
+}
+
+impl WorldGenConfig {
+    // default world with a different seed
+    pub fn with_seed(seed: u32) -> Self {
+        WorldGenConfig {
+            seed,
+            ..WorldGenConfig::default()
+        }
+    }
+
+    // read the world settings from the command line
+    // usage: rust_rpg [--seed N] [--size N] [--shape island|archipelago|continent|wrap] [--erode]
+    pub fn from_args(args: &[String]) -> Self {
+        let mut config = WorldGenConfig::default();
+        let mut i = 0;
+        while i < args.len() {
+            match (args[i].as_str(), args.get(i + 1)) {
+                ("--seed", Some(value)) => {
+                    match value.parse() {
+                        Ok(seed) => config.seed = seed,
+                        Err(_) => println!("Invalid seed: {}", value),
+                    }
+                    i += 1;
+                }
+                ("--size", Some(value)) => {
+                    // the map has to be bigger than the boundary around it
+                    match value.parse::<i32>() {
+                        Ok(size) if size > config.boundary_margin + config.far_boundary_margin => {
+                            config.width = size;
+                            config.height = size;
+                        }
+                        _ => println!("Invalid size: {}", value),
+                    }
+                    i += 1;
+                }
+                ("--shape", Some(value)) => {
+                    match value.parse() {
+                        Ok(shape) => config.shape = shape,
+                        Err(message) => println!("{}", message),
+                    }
+                    i += 1;
+                }
+                ("--erode", _) => config.erosion = Some(ErosionConfig::default()),
+                _ => (),
+            }
+            i += 1;
+        }
+        config
+    }
+
+    // is x,y inside the map at all
+    pub fn in_bounds(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && x < self.width && y < self.height
+    }
+
+    // is x,y within the boundary margin at the edge of the map,
+    // a wrap-around world only has boundaries at the top and bottom
+    pub fn is_boundary(&self, x: i32, y: i32) -> bool {
+        let (near, far) = (self.boundary_margin, self.far_boundary_margin);
+        let at_side = x < near || x >= self.width - far;
+        (at_side && !self.wraps()) || y < near || y >= self.height - far
+    }
+
+    // does walking off the east edge bring you back on the west edge
+    pub fn wraps(&self) -> bool {
+        self.shape == WorldShape::Wrap
+    }
+
+    // x brought back onto the map in a wrap-around world, unchanged otherwise
+    pub fn wrap_x(&self, x: i32) -> i32 {
+        if self.wraps() { x.rem_euclid(self.width) } else { x }
+    }
+}
+
+// the layout of land and sea, see shape.rs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WorldShape {
+    // one round island in the middle of the map
+    Island,
+    // several islands of different sizes
+    Archipelago,
+    // one big landmass with a ragged coast
+    Continent,
+    // land and sea everywhere, the east and west edges join up
+    Wrap,
+}
+
+impl FromStr for WorldShape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "island" => Ok(WorldShape::Island),
+            "archipelago" => Ok(WorldShape::Archipelago),
+            "continent" => Ok(WorldShape::Continent),
+            "wrap" => Ok(WorldShape::Wrap),
+            _ => Err(format!("Invalid shape: {}", s)),
+        }
+    }
+}
+
+// settings for the erosion stage
+#[derive(Clone, Debug, PartialEq)]
+pub struct ErosionConfig {
+    // how many water droplets roll over the whole map
+    pub droplets: usize,
+    // how many steps a droplet takes before it dries up
+    pub droplet_lifetime: usize,
+    // how much soil a droplet can carry for its speed and water
+    pub capacity: f64,
+    // how quickly a droplet digs in and drops its soil, 0-1
+    pub erosion_rate: f64,
+    pub deposition_rate: f64,
+    // how many times the slopes are left to slump
+    pub thermal_iterations: usize,
+    // the steepest height difference between neighbours that stays put
+    pub talus: f64,
+    // how much of the extra height slumps each iteration, 0-1
+    pub thermal_rate: f64,
+}
+
+impl Default for ErosionConfig {
+    fn default() -> Self {
+        ErosionConfig {
+            droplets: 1_000_000,
+            droplet_lifetime: 40,
+            capacity: 8.0,
+            erosion_rate: 0.3,
+            deposition_rate: 0.3,
+            thermal_iterations: 10,
+            talus: 0.2,
+            thermal_rate: 0.5,
+        }
+    }
+}
+
+// the independent noise fields a world is built from, all derived from the one seed
+#[derive(Clone, Copy, Debug)]
+pub struct WorldNoise {
+    pub elevation: Perlin,
+    pub moisture: Perlin,
+    pub temperature: Perlin,
+    // small scale roughness that makes rivers wander
+    pub meander: Perlin,
+}
+
+impl WorldNoise {
+    pub fn new(seed: u32) -> Self {
+        WorldNoise {
+            elevation: Perlin::new(seed),
+            moisture: Perlin::new(seed.wrapping_add(1)),
+            temperature: Perlin::new(seed.wrapping_add(2)),
+            meander: Perlin::new(seed.wrapping_add(3)),
+        }
+    }
+}
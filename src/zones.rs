@@ -0,0 +1,158 @@
+// Path: src/zones.rs
+
+use std::collections::HashMap;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use crate::{GameMap, GameObject, TerrainType, Tile};
+
+// ===========================================================================
+// Zones and spawn points
+// every tile is safe, wild or dangerous. settlements, their surroundings and the
+// roads between them are safe, harsh land and land far from any settlement is
+// dangerous, and everywhere underground is dangerous.
+// spawn points mark where monsters, items, characters and NPCs appear,
+// they are chosen once when the world is generated.
+
+// land this close to the edge of a settlement is still safe
+const SAFE_MARGIN: i32 = 16;
+// land further than this from every settlement is dangerous
+const DANGER_DISTANCE: i32 = 300;
+// how many random spots are tried for each spawn point before giving up
+const SPAWN_ATTEMPTS: usize = 50;
+// how far around a place safe ground is looked for
+const SAFE_SEARCH: i32 = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Zone {
+    // no fighting, no monsters
+    Safe,
+    Wild,
+    // where the monsters are
+    Danger,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpawnKind {
+    Monster,
+    Item,
+    // where a new player character starts
+    Character,
+    Npc,
+}
+
+impl SpawnKind {
+    const ALL: [SpawnKind; 4] = [SpawnKind::Monster, SpawnKind::Item, SpawnKind::Character, SpawnKind::Npc];
+}
+
+// fill in the zone and spawn point of a freshly generated tile
+pub fn annotate(map: &GameMap, tile: Tile) -> Tile {
+    let zone = zone_of(map, &tile);
+    let spawn = map.spawns.get(&(tile.x_position, tile.y_position)).copied();
+    Tile { zone, spawn, ..tile }
+}
+
+fn zone_of(map: &GameMap, tile: &Tile) -> Zone {
+    if map.depth > 0 {
+        return Zone::Danger;
+    }
+    let (x, y) = (tile.x_position, tile.y_position);
+    if matches!(tile.terrain_type, TerrainType::City | TerrainType::Road | TerrainType::Bridge) {
+        return Zone::Safe;
+    }
+
+    // how far it is to the edge of the nearest settlement
+    let nearest = map.settlements.iter()
+        .map(|settlement| {
+            let (dx, dy) = ((x - settlement.x) as f64, (y - settlement.y) as f64);
+            (dx * dx + dy * dy).sqrt() as i32 - settlement.size.radius()
+        })
+        .min();
+    match nearest {
+        Some(distance) if distance <= SAFE_MARGIN => Zone::Safe,
+        Some(distance) if distance <= DANGER_DISTANCE && !is_harsh(tile.terrain_type) => Zone::Wild,
+        _ => Zone::Danger,
+    }
+}
+
+// land that is hard going is where the monsters live
+fn is_harsh(terrain_type: TerrainType) -> bool {
+    matches!(terrain_type,
+        TerrainType::Mountain | TerrainType::Snow | TerrainType::Swamp | TerrainType::Jungle | TerrainType::Desert)
+}
+
+// choose the spawn points for a map, reads the settlements so it runs after them
+pub fn place_spawns(map: &GameMap) -> HashMap<(i32, i32), SpawnKind> {
+    let config = map.config();
+    let mut spawns = HashMap::new();
+    for (index, kind) in SpawnKind::ALL.iter().enumerate() {
+        let mut rng = StdRng::seed_from_u64((config.seed as u64) << 8 | (0x90 + index as u64));
+        let mut placed = 0;
+        for _ in 0..config.spawn_points * SPAWN_ATTEMPTS {
+            if placed >= config.spawn_points {
+                break;
+            }
+            let Some((x, y)) = pick_spot(map, &mut rng, *kind) else { break };
+            if spawns.contains_key(&(x, y)) {
+                continue;
+            }
+            let tile = map.generate_tile(x, y);
+            if tile.is_land() && allows(*kind, zone_of(map, &tile)) {
+                spawns.insert((x, y), *kind);
+                placed += 1;
+            }
+        }
+    }
+    spawns
+}
+
+// somewhere that might suit a spawn point of this kind
+fn pick_spot(map: &GameMap, rng: &mut StdRng, kind: SpawnKind) -> Option<(i32, i32)> {
+    let config = map.config();
+    match kind {
+        // people live in the settlements
+        SpawnKind::Character | SpawnKind::Npc => {
+            let settlement = map.settlements.choose(rng)?;
+            let radius = settlement.size.radius();
+            Some((settlement.x + rng.gen_range(-radius..=radius), settlement.y + rng.gen_range(-radius..=radius)))
+        }
+        SpawnKind::Monster | SpawnKind::Item => {
+            Some((rng.gen_range(0..config.width), rng.gen_range(0..config.height)))
+        }
+    }
+}
+
+// monsters never appear in safe zones, and items lying about would be picked up there
+fn allows(kind: SpawnKind, zone: Zone) -> bool {
+    match kind {
+        SpawnKind::Monster | SpawnKind::Item => zone != Zone::Safe,
+        SpawnKind::Character | SpawnKind::Npc => zone == Zone::Safe,
+    }
+}
+
+// the spawn points of one kind, in order of position
+pub fn spawn_points(map: &GameMap, kind: SpawnKind) -> Vec<(i32, i32)> {
+    let mut points: Vec<(i32, i32)> = map.spawns.iter()
+        .filter(|(_, spawn)| **spawn == kind)
+        .map(|(position, _)| *position)
+        .collect();
+    points.sort();
+    points
+}
+
+// the safe land nearest x,y, ring by ring out to SAFE_SEARCH, None when there is none that close
+pub fn nearest_safe(map: &GameMap, x: i32, y: i32) -> Option<(i32, i32)> {
+    let config = map.config();
+    (0..=SAFE_SEARCH).find_map(|ring| {
+        (-ring..=ring)
+            .flat_map(|dy| (-ring..=ring).map(move |dx| (dx, dy)))
+            .filter(|(dx, dy)| dx.abs() == ring || dy.abs() == ring)
+            .map(|(dx, dy)| (config.wrap_x(x + dx), y + dy))
+            .filter(|&(x, y)| config.in_bounds(x, y))
+            .find(|&(x, y)| {
+                let tile = map.get_tile(x, y);
+                tile.is_land() && tile.zone == Zone::Safe
+            })
+    })
+}